@@ -0,0 +1,95 @@
+// Copyright (c) 2023 Richard Cook
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+use crate::error::RepoError;
+use crate::result::RepoResult;
+use chrono::{DateTime, Utc};
+use fs4::FileExt;
+use serde::{Deserialize, Serialize};
+use std::fs::{read_to_string, remove_file, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const ACCESS_STATS_FILE_NAME: &str = "access.yaml";
+
+// Kept out of the manifest so that read-only handles can record access
+// without rewriting the manifest: the file is updated in place under its
+// own lock which is only ever held for the duration of a single update
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct AccessStats {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_accessed_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    access_count: u64,
+}
+
+impl AccessStats {
+    #[must_use]
+    pub const fn last_accessed_at(&self) -> Option<&DateTime<Utc>> {
+        self.last_accessed_at.as_ref()
+    }
+
+    #[must_use]
+    pub const fn access_count(&self) -> u64 {
+        self.access_count
+    }
+
+    pub(crate) fn read(data_dir: &Path) -> Self {
+        read_to_string(data_dir.join(ACCESS_STATS_FILE_NAME))
+            .ok()
+            .and_then(|s| serde_yaml::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn record(data_dir: &Path, now: DateTime<Utc>) -> RepoResult<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(data_dir.join(ACCESS_STATS_FILE_NAME))
+            .map_err(RepoError::other)?;
+        FileExt::lock_exclusive(&file).map_err(RepoError::other)?;
+
+        let mut s = String::new();
+        file.read_to_string(&mut s).map_err(RepoError::other)?;
+        let mut stats = serde_yaml::from_str::<Self>(&s).unwrap_or_default();
+        stats.last_accessed_at = Some(now);
+        stats.access_count += 1;
+
+        let yaml_str = serde_yaml::to_string(&stats).map_err(RepoError::other)?;
+        file.set_len(0).map_err(RepoError::other)?;
+        file.seek(SeekFrom::Start(0)).map_err(RepoError::other)?;
+        file.write_all(yaml_str.as_bytes())
+            .map_err(RepoError::other)?;
+        file.flush().map_err(RepoError::other)?;
+        _ = FileExt::unlock(&file);
+        Ok(stats)
+    }
+
+    pub(crate) fn reset(data_dir: &Path) -> RepoResult<()> {
+        let path = data_dir.join(ACCESS_STATS_FILE_NAME);
+        if path.is_file() {
+            remove_file(&path).map_err(|_e| RepoError::could_not_delete_file(&path))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,356 @@
+// Copyright (c) 2023 Richard Cook
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+use crate::error::RepoError;
+use crate::manifest::Manifest;
+use crate::meta_id::MetaId;
+use crate::result::RepoResult;
+use crate::schema::CURRENT_SCHEMA_VERSION;
+use chrono::{DateTime, Utc};
+use joatmon::read_yaml_file;
+use serde::{Deserialize, Serialize};
+use std::fs::{create_dir_all, read_dir, rename};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use tar::{Archive, Builder, Header};
+
+const RECORD_FILE_NAME: &str = "joat-repo-archive.yaml";
+const DATA_DIR_NAME: &str = "data";
+const SHARED_DIR_NAME: &str = "shared";
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ArchiveCompression {
+    #[default]
+    None,
+    Zstd,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ExportOptions {
+    pub compression: ArchiveCompression,
+    pub include_shared: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ImportOptions {
+    pub new_meta_id: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ArchiveRecord {
+    schema_version: u32,
+    exported_at: DateTime<Utc>,
+    pub(crate) meta_id: MetaId,
+}
+
+#[derive(Debug)]
+pub struct UnpackedArchive {
+    pub(crate) record: ArchiveRecord,
+    pub(crate) data_dir: PathBuf,
+    pub(crate) shared_dir: PathBuf,
+}
+
+pub fn write_archive<W>(
+    writer: W,
+    manifest: &Manifest,
+    meta_shared_dir: &Path,
+    options: &ExportOptions,
+) -> RepoResult<()>
+where
+    W: Write,
+{
+    write_tar_stream(writer, options.compression, |builder| {
+        let record = ArchiveRecord {
+            schema_version: CURRENT_SCHEMA_VERSION,
+            exported_at: Utc::now(),
+            meta_id: manifest.meta_id().clone(),
+        };
+        append_yaml(builder, RECORD_FILE_NAME, &record)?;
+
+        builder
+            .append_dir_all(DATA_DIR_NAME, manifest.data_dir())
+            .map_err(RepoError::other)?;
+
+        if options.include_shared && meta_shared_dir.is_dir() {
+            builder
+                .append_dir_all(SHARED_DIR_NAME, meta_shared_dir)
+                .map_err(RepoError::other)?;
+        }
+
+        Ok(())
+    })
+}
+
+pub fn unpack_archive<R>(reader: R, dir: &Path) -> RepoResult<UnpackedArchive>
+where
+    R: Read,
+{
+    unpack_tar_stream(reader, dir)?;
+
+    let record_path = dir.join(RECORD_FILE_NAME);
+    if !record_path.is_file() {
+        return Err(RepoError::invalid_archive("archive record is missing"));
+    }
+
+    let record = read_yaml_file::<ArchiveRecord>(&record_path)
+        .map_err(|e| RepoError::invalid_file(&record_path, &e))?;
+    if record.schema_version > CURRENT_SCHEMA_VERSION {
+        return Err(RepoError::unsupported_schema_version(
+            &record_path,
+            record.schema_version,
+            CURRENT_SCHEMA_VERSION,
+        ));
+    }
+
+    let data_dir = dir.join(DATA_DIR_NAME);
+    if !data_dir.is_dir() {
+        return Err(RepoError::invalid_archive("data directory is missing"));
+    }
+
+    Ok(UnpackedArchive {
+        record,
+        data_dir,
+        shared_dir: dir.join(SHARED_DIR_NAME),
+    })
+}
+
+pub fn write_tar_stream<W, F>(writer: W, compression: ArchiveCompression, f: F) -> RepoResult<()>
+where
+    W: Write,
+    F: FnOnce(&mut Builder<&mut dyn Write>) -> RepoResult<()>,
+{
+    match compression {
+        ArchiveCompression::None => {
+            let mut writer = writer;
+            write_tar(&mut writer, f)?;
+            writer.flush().map_err(RepoError::other)
+        }
+        ArchiveCompression::Zstd => {
+            let mut encoder = zstd::Encoder::new(writer, 0).map_err(RepoError::other)?;
+            write_tar(&mut encoder, f)?;
+            encoder
+                .finish()
+                .map_err(RepoError::other)?
+                .flush()
+                .map_err(RepoError::other)
+        }
+    }
+}
+
+pub fn unpack_tar_stream<R>(reader: R, dir: &Path) -> RepoResult<()>
+where
+    R: Read,
+{
+    let mut reader = BufReader::new(reader);
+    let is_zstd = reader
+        .fill_buf()
+        .map_err(RepoError::other)?
+        .starts_with(&ZSTD_MAGIC);
+    if is_zstd {
+        let decoder = zstd::Decoder::with_buffer(reader).map_err(RepoError::other)?;
+        Archive::new(decoder).unpack(dir)
+    } else {
+        Archive::new(reader).unpack(dir)
+    }
+    .map_err(RepoError::other)
+}
+
+pub fn append_yaml<T>(
+    builder: &mut Builder<&mut dyn Write>,
+    name: &str,
+    value: &T,
+) -> RepoResult<()>
+where
+    T: Serialize,
+{
+    let yaml_str = serde_yaml::to_string(value).map_err(RepoError::other)?;
+    let mut header = Header::new_gnu();
+    header.set_size(yaml_str.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(u64::try_from(Utc::now().timestamp()).unwrap_or_default());
+    header.set_cksum();
+    builder
+        .append_data(&mut header, name, yaml_str.as_bytes())
+        .map_err(RepoError::other)
+}
+
+pub fn merge_dir(from_dir: &Path, to_dir: &Path) -> RepoResult<()> {
+    if !from_dir.is_dir() {
+        return Ok(());
+    }
+
+    create_dir_all(to_dir).map_err(RepoError::other)?;
+    for e in read_dir(from_dir).map_err(RepoError::other)? {
+        let e = e.map_err(RepoError::other)?;
+        let target_path = to_dir.join(e.file_name());
+        if e.file_type().map_err(RepoError::other)?.is_dir() {
+            merge_dir(&e.path(), &target_path)?;
+        } else if !target_path.exists() {
+            rename(e.path(), &target_path).map_err(RepoError::other)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_tar<F>(writer: &mut dyn Write, f: F) -> RepoResult<()>
+where
+    F: FnOnce(&mut Builder<&mut dyn Write>) -> RepoResult<()>,
+{
+    let mut builder = Builder::new(writer);
+    builder.follow_symlinks(false);
+    f(&mut builder)?;
+    builder.finish().map_err(RepoError::other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ArchiveCompression, ExportOptions, ImportOptions};
+    use crate::config::RepoConfig;
+    use crate::shared_path::SharedPath;
+    use anyhow::Result;
+    use rstest::rstest;
+    use std::fs::{create_dir_all, read_to_string, write};
+    use tempdir::TempDir;
+
+    #[rstest]
+    #[case(ArchiveCompression::None)]
+    #[case(ArchiveCompression::Zstd)]
+    fn export_import(#[case] compression: ArchiveCompression) -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let project_dir = temp_dir.path().join("project");
+        create_dir_all(&project_dir)?;
+
+        let source_repo =
+            RepoConfig::default(&temp_dir.path().join("source"), None).repo_blocking()?;
+        let dir_info = source_repo.init(&project_dir)?.expect("must succeed");
+        create_dir_all(dir_info.data_dir().join("sub"))?;
+        write(dir_info.data_dir().join("sub").join("file.txt"), "content")?;
+        let shared_path = SharedPath::new(&format!("{}/shared.txt", dir_info.meta_id()));
+        source_repo.write_shared_file(&shared_path, "shared")?;
+        source_repo.write_shared_file(&SharedPath::new("other.txt"), "other")?;
+
+        let mut archive = Vec::new();
+        source_repo.export(
+            dir_info.meta_id(),
+            &mut archive,
+            &ExportOptions {
+                compression,
+                include_shared: true,
+            },
+        )?;
+
+        let data_dir_lock = source_repo
+            .lock_data_dir(dir_info.meta_id())?
+            .expect("must lock");
+        let err = source_repo
+            .export(dir_info.meta_id(), Vec::new(), &ExportOptions::default())
+            .expect_err("must fail");
+        assert!(err.is_could_not_lock());
+        drop(data_dir_lock);
+
+        let target_repo =
+            RepoConfig::default(&temp_dir.path().join("target"), None).repo_blocking()?;
+        let imported = target_repo
+            .import(archive.as_slice(), &project_dir, &ImportOptions::default())?
+            .expect("must succeed");
+        assert_eq!(dir_info.meta_id(), imported.meta_id());
+        assert_eq!(
+            "content",
+            read_to_string(imported.data_dir().join("sub").join("file.txt"))?
+        );
+        assert_eq!(
+            Some(String::from("shared")),
+            target_repo.read_shared_file(&shared_path)?
+        );
+        assert!(target_repo
+            .read_shared_file(&SharedPath::new("other.txt"))?
+            .is_none());
+        assert_eq!(
+            imported.meta_id(),
+            target_repo
+                .get(&project_dir)?
+                .expect("must exist")
+                .meta_id()
+        );
+        assert!(target_repo.check()?.is_empty());
+
+        let other_project_dir = temp_dir.path().join("other");
+        create_dir_all(&other_project_dir)?;
+        let err = target_repo
+            .import(
+                archive.as_slice(),
+                &other_project_dir,
+                &ImportOptions::default(),
+            )
+            .expect_err("must fail");
+        assert!(err.is_duplicate_meta_id());
+        assert!(target_repo.get(&other_project_dir)?.is_none());
+
+        let copy = target_repo
+            .import(
+                archive.as_slice(),
+                &other_project_dir,
+                &ImportOptions { new_meta_id: true },
+            )?
+            .expect("must succeed");
+        assert_ne!(imported.meta_id(), copy.meta_id());
+        assert_eq!(
+            Some(String::from("shared")),
+            target_repo
+                .read_shared_file(&SharedPath::new(&format!("{}/shared.txt", copy.meta_id())))?
+        );
+        assert_eq!(
+            copy.meta_id(),
+            target_repo.read_manifest(copy.meta_id())?.meta_id()
+        );
+        assert_eq!(2, target_repo.list_manifests()?.len());
+        assert!(target_repo.check()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn import_invalid_archive() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let repo = RepoConfig::default(&temp_dir.path().join("repo"), None).repo_blocking()?;
+        let err = repo
+            .import(&[0u8; 1024][..], temp_dir.path(), &ImportOptions::default())
+            .expect_err("must fail");
+        assert!(err.is_invalid_archive());
+        assert!(repo.list_manifests()?.is_empty());
+        assert!(repo.check()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn stale_import_staging_removed() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let config = RepoConfig::default(&temp_dir.path().join("repo"), None);
+        let staging_dir = config.container_dir.join(".import-stale");
+        create_dir_all(&staging_dir)?;
+        write(staging_dir.join("file.txt"), "content")?;
+
+        let repo = config.repo_blocking()?;
+        assert!(!staging_dir.exists());
+        assert!(repo.container_dir().is_dir());
+        Ok(())
+    }
+}
@@ -0,0 +1,540 @@
+// Copyright (c) 2023 Richard Cook
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+use crate::archive::{append_yaml, unpack_tar_stream, write_tar_stream, ArchiveCompression};
+use crate::config::RepoConfig;
+use crate::data_dir_lock::DataDirLock;
+use crate::error::RepoError;
+use crate::meta_id::MetaId;
+use crate::result::RepoResult;
+use crate::schema::CURRENT_SCHEMA_VERSION;
+use chrono::{DateTime, Utc};
+use joatmon::read_yaml_file;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsStr;
+use std::fmt::Write as _;
+use std::fs::{create_dir_all, read_dir, read_link, remove_dir_all, rename, File};
+use std::io::{copy, Read, Write};
+use std::path::{Path, PathBuf};
+use tar::Builder;
+
+const RECORD_FILE_NAME: &str = "joat-repo-backup.yaml";
+const CHECKSUMS_FILE_NAME: &str = "checksums.yaml";
+const LINKS_DIR_NAME: &str = "links";
+const DATA_DIR_NAME: &str = "data";
+const SHARED_DIR_NAME: &str = "shared";
+const QUARANTINE_DIR_NAME: &str = "quarantine";
+
+#[derive(Debug, Deserialize, Serialize)]
+struct BackupRecord {
+    schema_version: u32,
+    created_at: DateTime<Utc>,
+    config: RepoConfig,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Checksums {
+    sha256: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    symlinks: BTreeMap<String, String>,
+}
+
+pub fn write_backup<W>(
+    writer: W,
+    config: &RepoConfig,
+    compression: ArchiveCompression,
+) -> RepoResult<usize>
+where
+    W: Write,
+{
+    let mut checksums = Checksums::default();
+    write_tar_stream(writer, compression, |builder| {
+        let record = BackupRecord {
+            schema_version: CURRENT_SCHEMA_VERSION,
+            created_at: Utc::now(),
+            config: config.clone(),
+        };
+        let yaml_str = serde_yaml::to_string(&record).map_err(RepoError::other)?;
+        checksums.sha256.insert(
+            String::from(RECORD_FILE_NAME),
+            format!("{:x}", Sha256::digest(yaml_str.as_bytes())),
+        );
+        append_yaml(builder, RECORD_FILE_NAME, &record)?;
+
+        for (name, dir) in repo_dirs(config) {
+            if dir.is_dir() {
+                append_tree(builder, Path::new(name), name, dir, &mut checksums)?;
+            }
+        }
+
+        append_yaml(builder, CHECKSUMS_FILE_NAME, &checksums)
+    })?;
+    Ok(checksums.sha256.len() + checksums.symlinks.len())
+}
+
+pub fn restore_backup<R>(reader: R, base_dir: &Path) -> RepoResult<RepoConfig>
+where
+    R: Read,
+{
+    if base_dir.is_dir()
+        && read_dir(base_dir)
+            .map_err(RepoError::other)?
+            .next()
+            .is_some()
+    {
+        return Err(RepoError::directory_not_empty(base_dir));
+    }
+
+    let staging_dir = base_dir.join(format!(".restore-{}", MetaId::random()));
+    create_dir_all(&staging_dir).map_err(RepoError::other)?;
+    let result = restore_staged(reader, &staging_dir, base_dir);
+    _ = remove_dir_all(&staging_dir);
+    result
+}
+
+fn restore_staged<R>(reader: R, staging_dir: &Path, base_dir: &Path) -> RepoResult<RepoConfig>
+where
+    R: Read,
+{
+    unpack_tar_stream(reader, staging_dir)?;
+
+    let checksums_path = staging_dir.join(CHECKSUMS_FILE_NAME);
+    if !checksums_path.is_file() {
+        return Err(RepoError::invalid_archive("checksum manifest is missing"));
+    }
+    let checksums = read_yaml_file::<Checksums>(&checksums_path)
+        .map_err(|e| RepoError::invalid_file(&checksums_path, &e))?;
+    verify_checksums(staging_dir, &checksums)?;
+
+    let record_path = staging_dir.join(RECORD_FILE_NAME);
+    if !checksums.sha256.contains_key(RECORD_FILE_NAME) {
+        return Err(RepoError::invalid_archive("backup record is missing"));
+    }
+    let record = read_yaml_file::<BackupRecord>(&record_path)
+        .map_err(|e| RepoError::invalid_file(&record_path, &e))?;
+    if record.schema_version > CURRENT_SCHEMA_VERSION {
+        return Err(RepoError::unsupported_schema_version(
+            &record_path,
+            record.schema_version,
+            CURRENT_SCHEMA_VERSION,
+        ));
+    }
+
+    let config = remap_config(&record.config, base_dir);
+    for (name, dir) in repo_dirs(&config) {
+        let source_dir = staging_dir.join(name);
+        if source_dir.is_dir() {
+            if let Some(parent) = dir.parent() {
+                create_dir_all(parent).map_err(RepoError::other)?;
+            }
+            rename(&source_dir, dir).map_err(RepoError::other)?;
+        }
+    }
+
+    config.save(false)?;
+    Ok(config)
+}
+
+fn repo_dirs(config: &RepoConfig) -> Vec<(&str, &Path)> {
+    let mut dirs = vec![
+        (LINKS_DIR_NAME, config.links_dir.as_path()),
+        (DATA_DIR_NAME, config.container_dir.as_path()),
+        (SHARED_DIR_NAME, config.shared_dir.as_path()),
+    ];
+    if let Some(quarantine_dir) = &config.quarantine_dir {
+        dirs.push((QUARANTINE_DIR_NAME, quarantine_dir));
+    }
+    dirs
+}
+
+fn append_tree(
+    builder: &mut Builder<&mut dyn Write>,
+    entry_path: &Path,
+    key: &str,
+    dir: &Path,
+    checksums: &mut Checksums,
+) -> RepoResult<()> {
+    builder
+        .append_dir(entry_path, dir)
+        .map_err(RepoError::other)?;
+
+    let mut entries = read_dir(dir)
+        .map_err(RepoError::other)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(RepoError::other)?;
+    entries.sort_by_key(std::fs::DirEntry::file_name);
+
+    for e in entries {
+        let path = e.path();
+        let file_name = e.file_name();
+        let child_entry_path = entry_path.join(&file_name);
+        let child_key = format!("{key}/{}", encode_name(&file_name)?);
+        let file_type = e.file_type().map_err(RepoError::other)?;
+        if file_type.is_dir() {
+            append_tree(builder, &child_entry_path, &child_key, &path, checksums)?;
+        } else if !DataDirLock::is_lock_file(&path) {
+            if file_type.is_symlink() {
+                checksums.symlinks.insert(child_key, link_target(&path)?);
+            } else {
+                checksums.sha256.insert(child_key, hex_digest(&path)?);
+            }
+            builder
+                .append_path_with_name(&path, &child_entry_path)
+                .map_err(RepoError::other)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Every entry unpacked from the archive must be listed in the checksum
+// manifest with matching contents and every listed entry must be present
+fn verify_checksums(staging_dir: &Path, checksums: &Checksums) -> RepoResult<()> {
+    let mut unverified = checksums
+        .sha256
+        .keys()
+        .chain(checksums.symlinks.keys())
+        .map(String::as_str)
+        .collect::<BTreeSet<_>>();
+    verify_tree(staging_dir, None, checksums, &mut unverified)?;
+    match unverified.first() {
+        Some(name) => Err(RepoError::invalid_archive(&format!("{name} is missing"))),
+        None => Ok(()),
+    }
+}
+
+fn verify_tree(
+    dir: &Path,
+    prefix: Option<&str>,
+    checksums: &Checksums,
+    unverified: &mut BTreeSet<&str>,
+) -> RepoResult<()> {
+    for e in read_dir(dir).map_err(RepoError::other)? {
+        let e = e.map_err(RepoError::other)?;
+        let path = e.path();
+        let file_name = encode_name(&e.file_name())?;
+        let entry_name = match prefix {
+            Some(prefix) => format!("{prefix}/{file_name}"),
+            None => file_name,
+        };
+        if prefix.is_none() && entry_name == CHECKSUMS_FILE_NAME {
+            continue;
+        }
+
+        let file_type = e.file_type().map_err(RepoError::other)?;
+        if file_type.is_dir() {
+            verify_tree(&path, Some(&entry_name), checksums, unverified)?;
+            continue;
+        }
+
+        let is_valid = if file_type.is_symlink() {
+            checksums
+                .symlinks
+                .get(&entry_name)
+                .map(|expected| link_target(&path).is_ok_and(|t| t == *expected))
+        } else if file_type.is_file() {
+            checksums
+                .sha256
+                .get(&entry_name)
+                .map(|expected| hex_digest(&path).is_ok_and(|d| d == *expected))
+        } else {
+            None
+        };
+
+        let Some(is_valid) = is_valid else {
+            return Err(RepoError::invalid_archive(&format!(
+                "{entry_name} is not listed in checksum manifest"
+            )));
+        };
+        if !is_valid {
+            return Err(RepoError::invalid_archive(&format!(
+                "checksum mismatch for {entry_name}"
+            )));
+        }
+        unverified.remove(entry_name.as_str());
+    }
+    Ok(())
+}
+
+fn hex_digest(path: &Path) -> RepoResult<String> {
+    let mut hasher = Sha256::new();
+    let mut file = File::open(path).map_err(RepoError::other)?;
+    copy(&mut file, &mut hasher).map_err(RepoError::other)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn link_target(path: &Path) -> RepoResult<String> {
+    encode_name(read_link(path).map_err(RepoError::other)?.as_os_str())
+}
+
+// Checksum keys must identify file names exactly, so any bytes that are not
+// valid UTF-8 are percent-encoded along with the percent sign itself
+#[cfg(unix)]
+#[allow(clippy::unnecessary_wraps)]
+fn encode_name(name: &OsStr) -> RepoResult<String> {
+    use std::os::unix::ffi::OsStrExt;
+    let mut encoded = String::new();
+    for chunk in name.as_bytes().utf8_chunks() {
+        encoded.push_str(&chunk.valid().replace('%', "%25"));
+        for b in chunk.invalid() {
+            _ = write!(encoded, "%{b:02x}");
+        }
+    }
+    Ok(encoded)
+}
+
+#[cfg(not(unix))]
+fn encode_name(name: &OsStr) -> RepoResult<String> {
+    name.to_str().map(|s| s.replace('%', "%25")).ok_or_else(|| {
+        RepoError::invalid_archive(&format!(
+            "path {} is not valid Unicode",
+            name.to_string_lossy()
+        ))
+    })
+}
+
+fn remap_config(config: &RepoConfig, base_dir: &Path) -> RepoConfig {
+    let old_base_dir = config
+        .config_path
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    let remap = |path: &Path| -> PathBuf {
+        path.strip_prefix(&old_base_dir).map_or_else(
+            |_| base_dir.join(path.file_name().unwrap_or_default()),
+            |p| base_dir.join(p),
+        )
+    };
+
+    RepoConfig {
+        lock_path: remap(&config.lock_path),
+        config_path: remap(&config.config_path),
+        links_dir: remap(&config.links_dir),
+        container_dir: remap(&config.container_dir),
+        shared_dir: remap(&config.shared_dir),
+        quarantine_dir: config.quarantine_dir.as_deref().map(remap),
+        ..config.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{verify_checksums, Checksums, CHECKSUMS_FILE_NAME};
+    use crate::archive::{unpack_tar_stream, ArchiveCompression};
+    use crate::config::RepoConfig;
+    use crate::shared_path::SharedPath;
+    use crate::trash::Trash;
+    use anyhow::Result;
+    use joatmon::read_yaml_file;
+    use rstest::rstest;
+    use std::fs::{create_dir_all, read, read_to_string, write};
+    use tempdir::TempDir;
+
+    #[rstest]
+    #[case(ArchiveCompression::None)]
+    #[case(ArchiveCompression::Zstd)]
+    fn backup_restore(#[case] compression: ArchiveCompression) -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let project_dir = temp_dir.path().join("project");
+        create_dir_all(&project_dir)?;
+        let backup_path = temp_dir.path().join("backup.tar");
+
+        let repo =
+            RepoConfig::default(&temp_dir.path().join("repo"), Some("foo")).repo_blocking()?;
+        let dir_info = repo.init(&project_dir)?.expect("must succeed");
+        write(dir_info.data_dir().join("file.txt"), "content")?;
+        repo.write_shared_file(&SharedPath::new("shared.txt"), "shared")?;
+        assert_eq!(5, repo.backup(&backup_path, compression)?);
+        drop(repo);
+
+        let base_dir = temp_dir.path().join("restored");
+        let config = RepoConfig::restore_from(read(&backup_path)?.as_slice(), &base_dir)?;
+        assert_eq!(base_dir.join("foo-config.yaml"), config.config_path);
+        assert_eq!(base_dir.join("foo-links"), config.links_dir);
+        assert_eq!(base_dir.join("foo-data"), config.container_dir);
+        assert_eq!(Some(base_dir.join("foo-quarantine")), config.quarantine_dir);
+
+        let repo = RepoConfig::default(&base_dir, Some("foo")).repo_blocking()?;
+        let restored = repo.get(&project_dir)?.expect("must exist");
+        assert_eq!(dir_info.meta_id(), restored.meta_id());
+        assert!(restored.data_dir().starts_with(&base_dir));
+        assert_eq!(
+            "content",
+            read_to_string(restored.data_dir().join("file.txt"))?
+        );
+        assert_eq!(
+            Some(String::from("shared")),
+            repo.read_shared_file(&SharedPath::new("shared.txt"))?
+        );
+        assert!(repo.check()?.is_empty());
+
+        let err = RepoConfig::restore_from(read(&backup_path)?.as_slice(), &base_dir)
+            .expect_err("must fail");
+        assert!(err.is_directory_not_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn corrupt_backup() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let project_dir = temp_dir.path().join("project");
+        create_dir_all(&project_dir)?;
+        let backup_path = temp_dir.path().join("backup.tar");
+
+        let repo = RepoConfig::default(&temp_dir.path().join("repo"), None).repo_blocking()?;
+        let dir_info = repo.init(&project_dir)?.expect("must succeed");
+        write(dir_info.data_dir().join("file.txt"), "original-content")?;
+        repo.backup(&backup_path, ArchiveCompression::None)?;
+
+        let mut bytes = read(&backup_path)?;
+        let offset = bytes
+            .windows(16)
+            .position(|w| w == b"original-content")
+            .expect("must exist");
+        bytes[offset] = b'O';
+
+        let base_dir = temp_dir.path().join("restored");
+        let err = RepoConfig::restore_from(bytes.as_slice(), &base_dir).expect_err("must fail");
+        assert!(err.is_invalid_archive());
+        assert!(!base_dir.join("config.yaml").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn quarantine() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let project_dir = temp_dir.path().join("project");
+        create_dir_all(&project_dir)?;
+        let backup_path = temp_dir.path().join("backup.tar");
+
+        let repo = RepoConfig::default(&temp_dir.path().join("repo"), None).repo_blocking()?;
+        let dir_info = repo.init(&project_dir)?.expect("must succeed");
+        repo.remove(&project_dir)?;
+        assert_eq!(1, Trash::list_quarantine(&repo)?.len());
+        repo.backup(&backup_path, ArchiveCompression::None)?;
+
+        let base_dir = temp_dir.path().join("restored");
+        RepoConfig::restore_from(read(&backup_path)?.as_slice(), &base_dir)?;
+        let repo = RepoConfig::default(&base_dir, None).repo_blocking()?;
+        let quarantined = Trash::list_quarantine(&repo)?;
+        assert_eq!(1, quarantined.len());
+        assert_eq!(dir_info.meta_id(), quarantined[0].meta_id());
+        assert!(Trash::restore(&repo, dir_info.meta_id())?.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn unlisted_entry() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let project_dir = temp_dir.path().join("project");
+        create_dir_all(&project_dir)?;
+        let backup_path = temp_dir.path().join("backup.tar");
+
+        let repo = RepoConfig::default(&temp_dir.path().join("repo"), None).repo_blocking()?;
+        repo.init(&project_dir)?.expect("must succeed");
+        repo.backup(&backup_path, ArchiveCompression::None)?;
+
+        let staging_dir = temp_dir.path().join("staging");
+        unpack_tar_stream(read(&backup_path)?.as_slice(), &staging_dir)?;
+        let checksums = read_yaml_file::<Checksums>(&staging_dir.join(CHECKSUMS_FILE_NAME))?;
+        verify_checksums(&staging_dir, &checksums)?;
+
+        write(staging_dir.join("data").join("extra.txt"), "extra")?;
+        let err = verify_checksums(&staging_dir, &checksums).expect_err("must fail");
+        assert!(err.is_invalid_archive());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks() -> Result<()> {
+        use std::fs::{read_link, remove_file};
+        use std::os::unix::fs::symlink;
+        use std::path::Path;
+
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let project_dir = temp_dir.path().join("project");
+        create_dir_all(&project_dir)?;
+        let backup_path = temp_dir.path().join("backup.tar");
+
+        let repo = RepoConfig::default(&temp_dir.path().join("repo"), None).repo_blocking()?;
+        let dir_info = repo.init(&project_dir)?.expect("must succeed");
+        write(dir_info.data_dir().join("file.txt"), "content")?;
+        symlink("file.txt", dir_info.data_dir().join("link.txt"))?;
+        assert_eq!(5, repo.backup(&backup_path, ArchiveCompression::None)?);
+
+        let base_dir = temp_dir.path().join("restored");
+        RepoConfig::restore_from(read(&backup_path)?.as_slice(), &base_dir)?;
+        let repo = RepoConfig::default(&base_dir, None).repo_blocking()?;
+        let restored = repo.get(&project_dir)?.expect("must exist");
+        assert_eq!(
+            Path::new("file.txt"),
+            read_link(restored.data_dir().join("link.txt"))?
+        );
+
+        let staging_dir = temp_dir.path().join("staging");
+        unpack_tar_stream(read(&backup_path)?.as_slice(), &staging_dir)?;
+        let checksums = read_yaml_file::<Checksums>(&staging_dir.join(CHECKSUMS_FILE_NAME))?;
+        let link_path = staging_dir
+            .join("data")
+            .join(dir_info.meta_id().to_string())
+            .join("link.txt");
+        remove_file(&link_path)?;
+        symlink("/etc/passwd", &link_path)?;
+        let err = verify_checksums(&staging_dir, &checksums).expect_err("must fail");
+        assert!(err.is_invalid_archive());
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn non_unicode_names() -> Result<()> {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let project_dir = temp_dir.path().join("project");
+        create_dir_all(&project_dir)?;
+        let backup_path = temp_dir.path().join("backup.tar");
+
+        let repo = RepoConfig::default(&temp_dir.path().join("repo"), None).repo_blocking()?;
+        let dir_info = repo.init(&project_dir)?.expect("must succeed");
+        let names = [OsStr::from_bytes(b"caf\xe9.txt"), OsStr::new("caf%e9.txt")];
+        for (i, name) in names.iter().enumerate() {
+            write(dir_info.data_dir().join(name), format!("content{i}"))?;
+        }
+        assert_eq!(5, repo.backup(&backup_path, ArchiveCompression::None)?);
+
+        let base_dir = temp_dir.path().join("restored");
+        RepoConfig::restore_from(read(&backup_path)?.as_slice(), &base_dir)?;
+        let repo = RepoConfig::default(&base_dir, None).repo_blocking()?;
+        let restored = repo.get(&project_dir)?.expect("must exist");
+        for (i, name) in names.iter().enumerate() {
+            assert_eq!(
+                format!("content{i}"),
+                read_to_string(restored.data_dir().join(name))?
+            );
+        }
+        Ok(())
+    }
+}
@@ -19,10 +19,10 @@
 // OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
 // WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 //
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use clap::Parser;
 use clap::Subcommand as ClapSubcommand;
-use joat_repo::{MetaId, SharedPath};
+use joat_repo::SharedPath;
 use std::path::PathBuf;
 
 #[derive(Debug, Parser)]
@@ -41,9 +41,78 @@ pub struct Args {
 
 #[derive(ClapSubcommand, Debug)]
 pub enum Subcommand {
+    #[command(name = "backup", about = "Back up entire repository to archive")]
+    Backup {
+        #[arg(name = "output", help = "Path to archive")]
+        output_path: PathBuf,
+
+        #[arg(
+            long = "zstd",
+            default_value = "false",
+            help = "Compress archive with zstd"
+        )]
+        zstd: bool,
+    },
+
+    #[command(name = "check", about = "Check repository integrity")]
+    Check {
+        #[arg(
+            long = "repair",
+            default_value = "false",
+            help = "Apply suggested repairs"
+        )]
+        repair: bool,
+    },
+
+    #[command(name = "du", about = "Show disk usage of metadirectories")]
+    DiskUsage,
+
+    #[command(name = "export", about = "Export metadirectory to archive")]
+    Export {
+        #[arg(name = "output", help = "Path to archive")]
+        output_path: PathBuf,
+
+        #[arg(
+            long = "ref",
+            short = 'r',
+            help = "Metadirectory name, ID or unique ID prefix (defaults to current directory)"
+        )]
+        meta_ref: Option<String>,
+
+        #[arg(
+            long = "zstd",
+            default_value = "false",
+            help = "Compress archive with zstd"
+        )]
+        zstd: bool,
+
+        #[arg(
+            long = "shared",
+            default_value = "false",
+            help = "Include shared files belonging to metadirectory"
+        )]
+        include_shared: bool,
+    },
+
     #[command(name = "find", about = "Find parent metadirectory")]
     Find,
 
+    #[command(
+        name = "import",
+        about = "Import metadirectory from archive and link to current directory"
+    )]
+    Import {
+        #[arg(name = "archive", help = "Path to archive")]
+        archive_path: PathBuf,
+
+        #[arg(
+            long = "new-id",
+            default_value = "false",
+            help = "Assign new metadirectory ID instead of keeping original"
+        )]
+        new_meta_id: bool,
+    },
+
     #[command(name = "info", about = "Show configuration")]
     Info,
 
@@ -55,15 +124,50 @@ pub enum Subcommand {
         #[arg(
             long = "ref",
             short = 'r',
-            help = "Existing metadirectory ID",
-            value_parser = parse_meta_id
+            help = "Existing metadirectory name, ID or unique ID prefix"
         )]
-        meta_id: Option<MetaId>,
+        meta_ref: Option<String>,
     },
 
     #[command(name = "ls", about = "Show all metadirectory info")]
     List,
 
+    #[command(name = "migrate", about = "Upgrade repository files to current schema")]
+    Migrate,
+
+    #[command(name = "mv", about = "Move link to new project directory")]
+    Move {
+        #[arg(name = "from", help = "Original project directory")]
+        from_dir: PathBuf,
+
+        #[arg(
+            name = "to",
+            help = "New project directory (defaults to current directory)"
+        )]
+        to_dir: Option<PathBuf>,
+    },
+
+    #[command(name = "name", about = "Set or clear metadirectory name")]
+    Name {
+        #[arg(name = "name", help = "Name", required_unless_present = "clear")]
+        name: Option<String>,
+
+        #[arg(
+            long = "clear",
+            default_value = "false",
+            conflicts_with = "name",
+            help = "Clear name"
+        )]
+        clear: bool,
+
+        #[arg(
+            long = "ref",
+            short = 'r',
+            help = "Metadirectory name, ID or unique ID prefix (defaults to current directory)"
+        )]
+        meta_ref: Option<String>,
+    },
+
     #[command(name = "purge", about = "Purge repository")]
     Purge {
         #[arg(
@@ -80,8 +184,33 @@ pub enum Subcommand {
         path: SharedPath,
     },
 
+    #[command(name = "reconcile", about = "Repair link for moved project")]
+    Reconcile,
+
+    #[command(
+        name = "rehash",
+        about = "Rewrite links using configured hash algorithm"
+    )]
+    Rehash,
+
     #[command(name = "rm", about = "Unlink metadirectory")]
-    Remove,
+    Remove {
+        #[arg(
+            long = "empty-trash",
+            default_value = "false",
+            help = "Also clean up all other invalid links and unreferenced metadirectories"
+        )]
+        empty_trash: bool,
+    },
+
+    #[command(
+        name = "restore",
+        about = "Restore repository from backup archive into empty repository directory"
+    )]
+    Restore {
+        #[arg(name = "archive", help = "Path to archive")]
+        archive_path: PathBuf,
+    },
 
     #[command(name = "show", about = "Show metadirectory info")]
     Show,
@@ -90,8 +219,17 @@ pub enum Subcommand {
     Trash {
         #[arg(long = "clean", default_value = "false", help = "Clean up")]
         clean: bool,
+
+        #[arg(
+            long = "restore",
+            help = "Restore quarantined metadirectory by ID or unique ID prefix"
+        )]
+        restore: Option<String>,
     },
 
+    #[command(name = "unlock", about = "Break stale repository lock")]
+    Unlock,
+
     #[command(name = "write", about = "Save string to shared file")]
     Write {
         #[arg(name = "path", help = "Path", value_parser = parse_shared_path)]
@@ -102,8 +240,19 @@ pub enum Subcommand {
     },
 }
 
-fn parse_meta_id(s: &str) -> Result<MetaId> {
-    s.parse::<MetaId>().map_err(|e| anyhow!(e))
+impl Subcommand {
+    pub const fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Self::Check { repair: false }
+                | Self::DiskUsage
+                | Self::Export { .. }
+                | Self::Find
+                | Self::List
+                | Self::Read { .. }
+                | Self::Show
+        )
+    }
 }
 
 #[allow(clippy::unnecessary_wraps)]
@@ -0,0 +1,71 @@
+// Copyright (c) 2023 Richard Cook
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+use super::super::util::print;
+use super::super::Status;
+use anyhow::Result;
+use joat_repo::{CheckProblem, ReadOnlyRepo, Repo};
+use log::info;
+
+pub fn do_check(repo: &ReadOnlyRepo) -> Result<Status> {
+    let problems = repo.check()?;
+    print_problems(&problems);
+    Ok(if problems.is_empty() {
+        Status::Success
+    } else {
+        Status::Failure
+    })
+}
+
+pub fn do_repair(repo: &Repo) -> Result<Status> {
+    let problems = repo.check()?;
+    print_problems(&problems);
+    if problems.is_empty() {
+        return Ok(Status::Success);
+    }
+
+    let repair_count = repo.repair(&problems)?;
+    info!("Applied {repair_count} repairs");
+
+    Ok(if repair_count == problems.len() {
+        Status::Success
+    } else {
+        Status::Failure
+    })
+}
+
+fn print_problems(problems: &[CheckProblem]) {
+    if problems.is_empty() {
+        info!("No problems found");
+        return;
+    }
+
+    let problem_count = problems.len();
+    println!("The following {problem_count} problems were found:");
+    for (idx, problem) in problems.iter().enumerate() {
+        println!("({})", idx + 1);
+        print("Problem", problem);
+        match problem.repair() {
+            Some(repair) => print("Suggested repair", repair),
+            None => print("Suggested repair", "(manual repair required)"),
+        }
+    }
+}
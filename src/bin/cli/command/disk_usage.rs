@@ -0,0 +1,60 @@
+// Copyright (c) 2023 Richard Cook
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+use super::super::Status;
+use anyhow::Result;
+use colored::Colorize;
+use joat_repo::{DiskUsage, ReadOnlyRepo};
+
+pub fn do_disk_usage(repo: &ReadOnlyRepo) -> Result<Status> {
+    let report = repo.usage_report()?;
+
+    for manifest_usage in report.manifests() {
+        let manifest = manifest_usage.manifest();
+        println!(
+            "{}  {} ({})",
+            format_usage(manifest_usage.usage()).yellow(),
+            manifest.meta_id().to_string().green(),
+            manifest
+                .original_project_dir()
+                .display()
+                .to_string()
+                .bright_magenta()
+        );
+    }
+
+    println!(
+        "{}  {}",
+        format_usage(report.shared()).yellow(),
+        "(shared)".green()
+    );
+    println!(
+        "{}  {}",
+        format_usage(report.total()).yellow(),
+        "(total)".green()
+    );
+
+    Ok(Status::Success)
+}
+
+fn format_usage(usage: DiskUsage) -> String {
+    format!("{:>12} bytes {:>8} files", usage.bytes(), usage.files())
+}
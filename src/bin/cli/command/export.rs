@@ -0,0 +1,63 @@
+// Copyright (c) 2023 Richard Cook
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+use super::super::Status;
+use anyhow::Result;
+use joat_repo::{ArchiveCompression, ExportOptions, ReadOnlyRepo};
+use log::{error, info};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+pub fn do_export(
+    repo: &ReadOnlyRepo,
+    output_path: &Path,
+    meta_ref: Option<&str>,
+    zstd: bool,
+    include_shared: bool,
+    cwd: &Path,
+) -> Result<Status> {
+    let meta_id = if let Some(meta_ref) = meta_ref {
+        repo.resolve_meta_ref(meta_ref)?
+    } else if let Some(dir_info) = repo.get(cwd)? {
+        dir_info.meta_id().clone()
+    } else {
+        error!("No metadirectory found for directory {}", cwd.display());
+        return Ok(Status::Failure);
+    };
+
+    let options = ExportOptions {
+        compression: if zstd {
+            ArchiveCompression::Zstd
+        } else {
+            ArchiveCompression::None
+        },
+        include_shared,
+    };
+    let writer = BufWriter::new(File::create(output_path)?);
+    let manifest = repo.export(&meta_id, writer, &options)?;
+    info!(
+        "Exported metadirectory {} to {}",
+        manifest.meta_id(),
+        output_path.display()
+    );
+    Ok(Status::Success)
+}
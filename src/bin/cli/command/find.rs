@@ -22,43 +22,28 @@
 use super::super::util::print_data_dir;
 use super::super::Status;
 use anyhow::Result;
-use joat_repo::{Link, Repo};
-use log::error;
-use std::collections::HashMap;
+use joat_repo::ReadOnlyRepo;
+use log::{error, warn};
 use std::path::Path;
 
-fn find_link(repo: &Repo, dir: &Path) -> Result<Option<Link>> {
-    let mut map = repo
-        .list_links()?
-        .into_iter()
-        .map(|x| (x.project_dir().to_path_buf(), x))
-        .collect::<HashMap<_, _>>();
-
-    let mut d = dir;
-    loop {
-        if let Some(link) = map.remove(d) {
-            return Ok(Some(link));
-        }
-
-        if let Some(p) = d.parent() {
-            d = p;
-        } else {
-            return Ok(None);
-        }
-    }
-}
-
-pub fn do_find(repo: &Repo, cwd: &Path) -> Result<Status> {
-    let Some(link) = find_link(repo, cwd)? else {
+pub fn do_find(repo: &ReadOnlyRepo, cwd: &Path) -> Result<Status> {
+    let Some(dir_info) = repo.find(cwd)? else {
         error!("Could not find link for directory {}", cwd.display());
-        return Ok(Status::Failure);
-    };
-
-    let Some(dir_info) = repo.get(link.project_dir())? else {
-        error!(
-            "Could not find metadirectory info for directory {}",
-            link.project_dir().display()
-        );
+        if let Some(moved_project) = repo.detect_move(cwd)? {
+            if moved_project.is_copy() {
+                warn!(
+                    "Directory {} appears to be a copy of project {}: run \"init\" to create a new metadirectory",
+                    moved_project.project_dir().display(),
+                    moved_project.meta_id()
+                );
+                return Ok(Status::Failure);
+            }
+            warn!(
+                "Directory {} appears to be moved project {}: run \"reconcile\" to repair its link",
+                moved_project.project_dir().display(),
+                moved_project.meta_id()
+            );
+        }
         return Ok(Status::Failure);
     };
 
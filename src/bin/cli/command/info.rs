@@ -21,13 +21,36 @@
 //
 use super::super::util::print;
 use super::super::Status;
-use joat_repo::Repo;
+use joat_repo::LayeredConfig;
+use serde_yaml::Value;
 
-pub fn do_info(repo: &Repo) -> Status {
-    print("Lock file", repo.lock_path().display());
-    print("Configuration file", repo.config_path().display());
-    print("Links directory", repo.links_dir().display());
-    print("Container directory", repo.container_dir().display());
-    print("Shared directory", repo.shared_dir().display());
+pub fn do_info(config: &LayeredConfig) -> Status {
+    for entry in config.entries() {
+        print(
+            entry.key(),
+            format!("{} ({})", format_value(entry.value()), entry.source()),
+        );
+    }
     Status::Success
 }
+
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::Null => String::from("(none)"),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => s.clone(),
+        Value::Sequence(values) => format!(
+            "[{}]",
+            values
+                .iter()
+                .map(format_value)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        _ => serde_yaml::to_string(value)
+            .unwrap_or_default()
+            .trim()
+            .to_string(),
+    }
+}
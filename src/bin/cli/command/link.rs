@@ -36,25 +36,36 @@ fn prompt_for_meta_id(repo: &Repo) -> Result<Option<MetaId>> {
     }
 
     for (idx, manifest) in manifests.iter().enumerate() {
-        println!(
-            "({}): {}: {}",
-            idx + 1,
-            manifest.meta_id(),
-            manifest.original_project_dir().display()
-        );
+        match &manifest.metadata().name {
+            Some(name) => println!(
+                "({}): {} [{}]: {}",
+                idx + 1,
+                manifest.meta_id(),
+                name,
+                manifest.original_project_dir().display()
+            ),
+            None => println!(
+                "({}): {}: {}",
+                idx + 1,
+                manifest.meta_id(),
+                manifest.original_project_dir().display()
+            ),
+        }
     }
 
     let line = if manifest_count > 1 {
-        prompt(&format!("Enter 1-{manifest_count} or Q to quit"))?
+        prompt(&format!(
+            "Enter 1-{manifest_count}, a name or an ID prefix, or Q to quit"
+        ))?
     } else {
-        prompt("Enter 1 or Q to quit")?
+        prompt("Enter 1, a name or an ID prefix, or Q to quit")?
     };
     if line == "q" {
         return Ok(None);
     }
 
     let Ok(index) = line.parse::<usize>() else {
-        return Ok(None);
+        return Ok(Some(repo.resolve_meta_ref(&line)?));
     };
 
     if index < 1 || index > manifest_count {
@@ -64,7 +75,7 @@ fn prompt_for_meta_id(repo: &Repo) -> Result<Option<MetaId>> {
     Ok(Some(manifests[index - 1].meta_id().clone()))
 }
 
-pub fn do_link(repo: &Repo, meta_id: &Option<MetaId>, cwd: &Path) -> Result<Status> {
+pub fn do_link(repo: &Repo, meta_ref: Option<&str>, cwd: &Path) -> Result<Status> {
     if let Some(link) = repo.read_link(cwd)? {
         error!(
             "Link {} already exists for directory {}",
@@ -74,8 +85,8 @@ pub fn do_link(repo: &Repo, meta_id: &Option<MetaId>, cwd: &Path) -> Result<Stat
         return Ok(Status::Failure);
     }
 
-    let meta_id = match meta_id {
-        Some(value) => value.clone(),
+    let meta_id = match meta_ref {
+        Some(value) => repo.resolve_meta_ref(value)?,
         None => match prompt_for_meta_id(repo)? {
             Some(value) => value,
             None => return Ok(Status::Failure),
@@ -22,9 +22,9 @@
 use super::super::Status;
 use anyhow::Result;
 use colored::Colorize;
-use joat_repo::Repo;
+use joat_repo::ReadOnlyRepo;
 
-pub fn do_list(repo: &Repo) -> Result<Status> {
+pub fn do_list(repo: &ReadOnlyRepo) -> Result<Status> {
     let mut manifests = repo.list_manifests()?;
     manifests.sort_by_cached_key(|m| m.meta_id().clone());
     let manifests = manifests;
@@ -34,10 +34,21 @@ pub fn do_list(repo: &Repo) -> Result<Status> {
             format!("Metadirectories ({})", manifests.len()).green()
         );
         for manifest in manifests {
+            let last_accessed_at = manifest
+                .last_accessed_at()
+                .map_or_else(|| String::from("never"), ToString::to_string);
+            let name = manifest
+                .metadata()
+                .name
+                .as_ref()
+                .map_or_else(String::new, |name| format!(" [{name}]"));
             println!(
-                "  {} ({})",
+                "  {}{} ({})\n    last accessed {} ({} accesses)",
                 manifest.meta_id().to_string().yellow(),
-                manifest.data_dir().display().to_string().blue()
+                name.green(),
+                manifest.data_dir().display().to_string().blue(),
+                last_accessed_at.bright_magenta(),
+                manifest.access_count()
             );
         }
     }
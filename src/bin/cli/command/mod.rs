@@ -19,26 +19,50 @@
 // OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
 // WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 //
+mod backup;
+mod check;
+mod disk_usage;
+mod export;
 mod find;
+mod import;
 mod info;
 mod init;
 mod link;
 mod list;
+mod migrate;
+mod name;
 mod purge;
 mod read;
+mod reconcile;
+mod rehash;
+mod relink;
 mod remove;
+mod restore;
 mod show;
 mod trash;
+mod unlock;
 mod write;
 
+pub use self::backup::do_backup;
+pub use self::check::{do_check, do_repair};
+pub use self::disk_usage::do_disk_usage;
+pub use self::export::do_export;
 pub use self::find::do_find;
+pub use self::import::do_import;
 pub use self::info::do_info;
 pub use self::init::do_init;
 pub use self::link::do_link;
 pub use self::list::do_list;
+pub use self::migrate::do_migrate;
+pub use self::name::do_name;
 pub use self::purge::do_purge;
 pub use self::read::do_read;
+pub use self::reconcile::do_reconcile;
+pub use self::rehash::do_rehash;
+pub use self::relink::do_relink;
 pub use self::remove::do_remove;
+pub use self::restore::do_restore;
 pub use self::show::do_show;
 pub use self::trash::do_trash;
+pub use self::unlock::do_unlock;
 pub use self::write::do_write;
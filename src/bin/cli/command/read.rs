@@ -21,15 +21,15 @@
 //
 use super::super::Status;
 use anyhow::Result;
-use joat_repo::{Repo, SharedPath};
+use joat_repo::{ReadOnlyRepo, SharedPath};
 use log::error;
 
-pub fn do_read(repo: &Repo, path: &SharedPath) -> Result<Status> {
+pub fn do_read(repo: &ReadOnlyRepo, path: &SharedPath) -> Result<Status> {
     if let Some(s) = repo.read_shared_file(path)? {
         println!("{s}");
         Ok(Status::Success)
     } else {
-        error!("Shared file {} not found", path);
+        error!("Shared file {path} not found");
         Ok(Status::Failure)
     }
 }
@@ -0,0 +1,49 @@
+// Copyright (c) 2023 Richard Cook
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+use super::super::util::print_link;
+use super::super::Status;
+use anyhow::Result;
+use joat_repo::Repo;
+use log::info;
+
+pub fn do_rehash(repo: &Repo) -> Result<Status> {
+    let links = repo.rehash_links()?;
+    if links.is_empty() {
+        info!(
+            "All links already use {} hash algorithm",
+            repo.link_hash_algorithm()
+        );
+        return Ok(Status::Success);
+    }
+
+    println!(
+        "The following {} links were rewritten to use {} hash algorithm:",
+        links.len(),
+        repo.link_hash_algorithm()
+    );
+    for (idx, link) in links.iter().enumerate() {
+        println!("({})", idx + 1);
+        print_link(link);
+    }
+
+    Ok(Status::Success)
+}
@@ -0,0 +1,54 @@
+// Copyright (c) 2023 Richard Cook
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+use super::super::util::print_data_dir;
+use super::super::Status;
+use anyhow::Result;
+use joat_repo::Repo;
+use log::error;
+use path_absolutize::Absolutize;
+use std::path::Path;
+
+pub fn do_relink(
+    repo: &Repo,
+    from_dir: &Path,
+    to_dir: Option<&Path>,
+    cwd: &Path,
+) -> Result<Status> {
+    let from_dir = from_dir.absolutize_from(cwd)?;
+    let to_dir = to_dir.unwrap_or(cwd).absolutize_from(cwd)?;
+
+    if repo.get(&from_dir)?.is_none() {
+        error!(
+            "No metadirectory found for directory {}",
+            from_dir.display()
+        );
+        return Ok(Status::Failure);
+    }
+
+    Ok(if let Some(dir_info) = repo.relink(&from_dir, &to_dir)? {
+        print_data_dir(&dir_info);
+        Status::Success
+    } else {
+        error!("Directory {} is already in repository", to_dir.display());
+        Status::Failure
+    })
+}
@@ -19,17 +19,34 @@
 // OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
 // WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 //
+use super::super::util::{print_link, print_manifest};
 use super::super::Status;
 use anyhow::Result;
 use joat_repo::Repo;
-use log::error;
+use log::{error, info};
 use std::path::Path;
 
-pub fn do_remove(repo: &Repo, cwd: &Path) -> Result<Status> {
-    Ok(if repo.remove(cwd)? {
-        Status::Success
+pub fn do_remove(repo: &Repo, cwd: &Path, empty_trash: bool) -> Result<Status> {
+    let removal = if empty_trash {
+        repo.remove_and_empty_trash(cwd)?
     } else {
+        repo.remove(cwd)?
+    };
+
+    let Some(removal) = removal else {
         error!("No metadirectory found for directory {}", cwd.display());
-        Status::Failure
-    })
+        return Ok(Status::Failure);
+    };
+
+    for link in removal.links() {
+        info!("Removed link {}", link.link_id());
+        print_link(link);
+    }
+
+    for manifest in removal.manifests() {
+        info!("Removed metadirectory {}", manifest.meta_id());
+        print_manifest(manifest);
+    }
+
+    Ok(Status::Success)
 }
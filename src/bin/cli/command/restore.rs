@@ -0,0 +1,39 @@
+// Copyright (c) 2023 Richard Cook
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+use super::super::Status;
+use anyhow::Result;
+use joat_repo::RepoConfig;
+use log::info;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+pub fn do_restore(repo_dir: &Path, archive_path: &Path) -> Result<Status> {
+    let reader = BufReader::new(File::open(archive_path)?);
+    let config = RepoConfig::restore_from(reader, repo_dir)?;
+    info!(
+        "Restored repository from {} to {}",
+        archive_path.display(),
+        config.config_path.display()
+    );
+    Ok(Status::Success)
+}
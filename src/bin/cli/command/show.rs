@@ -22,13 +22,20 @@
 use super::super::util::print_data_dir;
 use super::super::Status;
 use anyhow::Result;
-use joat_repo::Repo;
-use log::error;
+use joat_repo::ReadOnlyRepo;
+use log::{error, warn};
 use std::path::Path;
 
-pub fn do_show(repo: &Repo, project_dir: &Path) -> Result<Status> {
+pub fn do_show(repo: &ReadOnlyRepo, project_dir: &Path) -> Result<Status> {
     Ok(if let Some(dir_info) = repo.get(project_dir)? {
         print_data_dir(&dir_info);
+        if dir_info.link_id().algorithm() != repo.link_hash_algorithm() {
+            warn!(
+                "Link uses {} hash algorithm: run \"rehash\" to rewrite it using {}",
+                dir_info.link_id().algorithm(),
+                repo.link_hash_algorithm()
+            );
+        }
         Status::Success
     } else {
         error!(
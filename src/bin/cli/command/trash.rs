@@ -19,37 +19,85 @@
 // OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
 // WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 //
-use super::super::util::{print_link, print_manifest};
+use super::super::util::{print, print_link, print_manifest};
 use super::super::Status;
 use anyhow::Result;
-use joat_repo::{Repo, Trash};
-use log::info;
+use joat_repo::{MetaId, Repo, Trash};
+use log::{error, info};
 
-pub fn do_trash(repo: &Repo, clean: bool) -> Result<Status> {
-    let mut trash = Trash::compute(repo)?;
+pub fn do_trash(repo: &Repo, clean: bool, restore: Option<&str>) -> Result<Status> {
+    if let Some(meta_ref) = restore {
+        let meta_id = Trash::resolve_quarantined_ref(repo, meta_ref)?;
+        return do_restore(repo, &meta_id);
+    }
+
+    let mut trash = Trash::compute_with_policy(repo, repo.trash_policy())?;
+
+    let quarantined = Trash::list_quarantine(repo)?;
+    let quarantined_count = quarantined.len();
+    if quarantined_count > 0 {
+        println!("The following {quarantined_count} metadirectories are quarantined:");
+        for (idx, entry) in quarantined.iter().enumerate() {
+            println!("({})", idx + 1);
+            print("Meta ID", entry.meta_id());
+            print("Quarantined at", entry.quarantined_at());
+            print("Reason", entry.reason());
+            for project_dir in entry.project_dirs() {
+                print("Project directory", project_dir.display());
+            }
+        }
+    }
+
+    let unreachable_link_count = trash.unreachable_links.len();
+    if unreachable_link_count > 0 {
+        println!("The following {unreachable_link_count} links are unreachable and will be kept:");
+        for (idx, entry) in trash.unreachable_links.iter().enumerate() {
+            println!("({})", idx + 1);
+            print_link(&entry.item);
+            print("Reason", entry.reason);
+        }
+    }
+
+    let idle_link_count = trash.idle_links.len();
+    if idle_link_count > 0 {
+        println!("The following {idle_link_count} links are idle and will be kept:");
+        for (idx, entry) in trash.idle_links.iter().enumerate() {
+            println!("({})", idx + 1);
+            print_link(&entry.item);
+            print("Reason", entry.reason);
+        }
+    }
 
     if trash.is_empty() {
         info!("No clean-up required");
         return Ok(Status::Success);
     }
 
+    let action = if trash.is_quarantined() {
+        "quarantined"
+    } else {
+        "removed"
+    };
+
     let invalid_link_count = trash.invalid_links.len();
     if invalid_link_count > 0 {
         println!("The following {invalid_link_count} links are invalid and will be removed:");
-        for (idx, link) in trash.invalid_links.iter().enumerate() {
+        for (idx, entry) in trash.invalid_links.iter().enumerate() {
             println!("({})", idx + 1);
-            print_link(link);
+            print_link(&entry.item);
+            print("Reason", entry.reason);
         }
     }
 
     let unreferenced_manifest_count = trash.unreferenced_manifests.len();
     if unreferenced_manifest_count > 0 {
         println!(
-            "The following {unreferenced_manifest_count} metadirectories are unreferenced and will be removed:"
+            "The following {unreferenced_manifest_count} metadirectories are unreferenced and will be {action}:"
         );
-        for (idx, manifest) in trash.unreferenced_manifests.iter().enumerate() {
+        for (idx, entry) in trash.unreferenced_manifests.iter().enumerate() {
             println!("({})", idx + 1);
-            print_manifest(manifest);
+            print_manifest(&entry.item);
+            print("Reason", entry.reason);
         }
     }
 
@@ -59,3 +107,14 @@ pub fn do_trash(repo: &Repo, clean: bool) -> Result<Status> {
 
     Ok(Status::Success)
 }
+
+fn do_restore(repo: &Repo, meta_id: &MetaId) -> Result<Status> {
+    if let Some(manifest) = Trash::restore(repo, meta_id)? {
+        info!("Restored metadirectory {meta_id}");
+        print_manifest(&manifest);
+        Ok(Status::Success)
+    } else {
+        error!("Could not restore metadirectory {meta_id}");
+        Ok(Status::Failure)
+    }
+}
@@ -0,0 +1,45 @@
+// Copyright (c) 2023 Richard Cook
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+use super::super::util::describe_lock_owner;
+use super::super::Status;
+use anyhow::Result;
+use joat_repo::RepoConfig;
+use log::{error, info};
+
+pub fn do_unlock(config: &RepoConfig) -> Result<Status> {
+    let Some(owner) = config.lock_owner()? else {
+        info!("Repository is not locked");
+        return Ok(Status::Success);
+    };
+
+    let description = describe_lock_owner(&owner);
+    Ok(if config.break_lock()? {
+        info!("Broke lock held by {description}");
+        Status::Success
+    } else if owner.is_alive() == Some(true) {
+        error!("Repository is locked by {description} which is still running");
+        Status::Failure
+    } else {
+        error!("Repository is locked by {description} which cannot be confirmed to have exited");
+        Status::Failure
+    })
+}
@@ -27,9 +27,10 @@ mod util;
 
 pub use self::args::{Args, Subcommand};
 pub use self::command::{
-    do_find, do_info, do_init, do_link, do_list, do_purge, do_read, do_remove, do_show, do_trash,
-    do_write,
+    do_backup, do_check, do_disk_usage, do_export, do_find, do_import, do_info, do_init, do_link,
+    do_list, do_migrate, do_name, do_purge, do_read, do_reconcile, do_rehash, do_relink, do_remove,
+    do_repair, do_restore, do_show, do_trash, do_unlock, do_write,
 };
 pub use self::logger::Logger;
 pub use self::status::Status;
-pub use self::util::prompt;
+pub use self::util::{describe_lock_owner, prompt};
@@ -20,8 +20,9 @@
 // WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 //
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use colored::Colorize;
-use joat_repo::{DirInfo, Link, Manifest};
+use joat_repo::{DirInfo, Link, LockMode, LockOwner, Manifest, Metadata};
 use std::fmt::Display;
 use std::io::{stdin, stdout, Write};
 
@@ -51,10 +52,25 @@ pub fn print_data_dir(dir_info: &DirInfo) {
         dir_info.original_project_dir().display(),
     );
     print("Meta ID", dir_info.meta_id());
+    print_access(dir_info.last_accessed_at(), dir_info.access_count());
     print("Link path", dir_info.link_path().display());
     print("Link created at", dir_info.link_created_at());
     print("Link ID", dir_info.link_id());
     print("Project directory", dir_info.project_dir().display());
+    print_metadata("Metadirectory", dir_info.manifest_metadata());
+    print_metadata("Link", dir_info.link_metadata());
+}
+
+fn print_metadata(label: &str, metadata: &Metadata) {
+    if let Some(name) = &metadata.name {
+        print(&format!("{label} name"), name);
+    }
+    if !metadata.tags.is_empty() {
+        print(&format!("{label} tags"), metadata.tags.join(", "));
+    }
+    for (key, value) in &metadata.values {
+        print(&format!("{label} {key}"), value);
+    }
 }
 
 pub fn print_manifest(manifest: &Manifest) {
@@ -66,12 +82,38 @@ pub fn print_manifest(manifest: &Manifest) {
         manifest.original_project_dir().display(),
     );
     print("Meta ID", manifest.meta_id());
+    if let Some(name) = &manifest.metadata().name {
+        print("Name", name);
+    }
+    print_access(manifest.last_accessed_at(), manifest.access_count());
+}
+
+fn print_access(last_accessed_at: Option<&DateTime<Utc>>, access_count: u64) {
+    match last_accessed_at {
+        Some(last_accessed_at) => print("Last accessed at", last_accessed_at),
+        None => print("Last accessed at", "(never)"),
+    }
+    print("Access count", access_count);
 }
 
 pub fn print_link(link: &Link) {
     print("Link path", link.link_path().display());
     print("Created at", link.created_at());
     print("Link ID", link.link_id());
+    print("Link hash algorithm", link.hash_algorithm());
     print("Project directory", link.project_dir().display());
     print("Meta ID", link.meta_id());
 }
+
+pub fn describe_lock_owner(owner: &LockOwner) -> String {
+    if owner.mode() == LockMode::Shared {
+        return String::from("one or more read-only processes");
+    }
+
+    match (owner.pid(), owner.host_name(), owner.is_alive()) {
+        (Some(pid), _, Some(false)) => format!("process {pid} which is no longer running"),
+        (Some(pid), Some(host_name), _) => format!("process {pid} on {host_name}"),
+        (Some(pid), None, _) => format!("process {pid}"),
+        (None, _, _) => String::from("unknown process"),
+    }
+}
@@ -30,25 +30,32 @@
 #![allow(clippy::match_wildcard_for_single_variants)]
 #![allow(clippy::missing_errors_doc)]
 #![allow(clippy::module_name_repetitions)]
+#![allow(clippy::multiple_crate_versions)]
 #![allow(clippy::option_if_let_else)]
+#![allow(clippy::struct_field_names)]
 mod cli;
 
 use crate::cli::{
-    do_find, do_info, do_init, do_link, do_list, do_purge, do_read, do_remove, do_show, do_trash,
-    do_write, Args, Logger, Status, Subcommand,
+    describe_lock_owner, do_backup, do_check, do_disk_usage, do_export, do_find, do_import,
+    do_info, do_init, do_link, do_list, do_migrate, do_name, do_purge, do_read, do_reconcile,
+    do_rehash, do_relink, do_remove, do_repair, do_restore, do_show, do_trash, do_unlock, do_write,
+    Args, Logger, Status, Subcommand,
 };
 use anyhow::{anyhow, Result};
 use clap::Parser;
-use joat_repo::{Repo, RepoConfig};
+use joat_repo::{ConfigLoader, ReadOnlyRepo, Repo};
 use log::error;
 use log::{set_logger, set_max_level, LevelFilter};
 use path_absolutize::Absolutize;
 use std::env::{current_dir, set_var, var, VarError};
 use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::time::Duration;
 
 static LOGGER: Logger = Logger;
 
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
 #[cfg(debug_assertions)]
 fn init_backtrace() {
     const RUST_BACKTRACE_ENV_NAME: &str = "RUST_BACKTRACE";
@@ -94,29 +101,97 @@ fn run() -> Result<Status> {
     let cwd = current_dir()?;
     let repo_dir = get_repo_dir(&cwd, &args)?;
 
-    if let Some(repo) = RepoConfig::default(&repo_dir, None).repo()? {
-        run_command(&args, &repo, &cwd)
+    let layered_config = ConfigLoader::new(&repo_dir, None).load()?;
+    let config = layered_config.config().clone();
+    if matches!(args.subcommand, Subcommand::Info) {
+        return Ok(do_info(&layered_config));
+    }
+    if matches!(args.subcommand, Subcommand::Unlock) {
+        return do_unlock(&config);
+    }
+    if let Subcommand::Restore { archive_path } = &args.subcommand {
+        return do_restore(&repo_dir, archive_path);
+    }
+
+    let result = if args.subcommand.is_read_only() {
+        layered_config
+            .read_only_repo_with_timeout(LOCK_TIMEOUT)
+            .map(|repo| run_read_only_command(&args, &repo, &cwd))
     } else {
-        error!(
-            "Repository at {} is currently in use by another program or lock file is invalid",
-            repo_dir.display()
-        );
-        Ok(Status::Failure)
+        layered_config
+            .repo_with_timeout(LOCK_TIMEOUT)
+            .map(|repo| run_command(&args, &repo, &cwd))
+    };
+
+    match result {
+        Ok(status) => status,
+        Err(e) if e.is_lock_timed_out() => {
+            if let Some(owner) = config.lock_owner()? {
+                error!(
+                    "Repository at {} is currently locked by {}",
+                    repo_dir.display(),
+                    describe_lock_owner(&owner)
+                );
+            } else {
+                error!(
+                    "Repository at {} is currently in use by another program or lock file is invalid",
+                    repo_dir.display()
+                );
+            }
+            Ok(Status::Failure)
+        }
+        Err(e) => Err(e.into()),
     }
 }
 
-fn run_command(args: &Args, repo: &Repo, cwd: &Path) -> Result<Status> {
+fn run_read_only_command(args: &Args, repo: &ReadOnlyRepo, cwd: &Path) -> Result<Status> {
     match &args.subcommand {
+        Subcommand::Check { .. } => do_check(repo),
+        Subcommand::DiskUsage => do_disk_usage(repo),
+        Subcommand::Export {
+            output_path,
+            meta_ref,
+            zstd,
+            include_shared,
+        } => do_export(
+            repo,
+            output_path,
+            meta_ref.as_deref(),
+            *zstd,
+            *include_shared,
+            cwd,
+        ),
         Subcommand::Find => do_find(repo, cwd),
-        Subcommand::Info => Ok(do_info(repo)),
-        Subcommand::Init => do_init(repo, cwd),
-        Subcommand::Link { meta_id } => do_link(repo, meta_id, cwd),
         Subcommand::List => do_list(repo),
-        Subcommand::Purge { force } => do_purge(repo, *force),
         Subcommand::Read { path } => do_read(repo, path),
-        Subcommand::Remove => do_remove(repo, cwd),
         Subcommand::Show => do_show(repo, cwd),
-        Subcommand::Trash { clean } => do_trash(repo, *clean),
+        _ => unreachable!(),
+    }
+}
+
+fn run_command(args: &Args, repo: &Repo, cwd: &Path) -> Result<Status> {
+    match &args.subcommand {
+        Subcommand::Backup { output_path, zstd } => do_backup(repo, output_path, *zstd),
+        Subcommand::Check { repair: true } => do_repair(repo),
+        Subcommand::Import {
+            archive_path,
+            new_meta_id,
+        } => do_import(repo, archive_path, *new_meta_id, cwd),
+        Subcommand::Init => do_init(repo, cwd),
+        Subcommand::Link { meta_ref } => do_link(repo, meta_ref.as_deref(), cwd),
+        Subcommand::Name {
+            name,
+            clear: _,
+            meta_ref,
+        } => do_name(repo, name.as_deref(), meta_ref.as_deref(), cwd),
+        Subcommand::Migrate => do_migrate(repo),
+        Subcommand::Move { from_dir, to_dir } => do_relink(repo, from_dir, to_dir.as_deref(), cwd),
+        Subcommand::Purge { force } => do_purge(repo, *force),
+        Subcommand::Reconcile => do_reconcile(repo, cwd),
+        Subcommand::Rehash => do_rehash(repo),
+        Subcommand::Remove { empty_trash } => do_remove(repo, cwd, *empty_trash),
+        Subcommand::Trash { clean, restore } => do_trash(repo, *clean, restore.as_deref()),
         Subcommand::Write { path, value } => do_write(repo, path, value),
+        _ => run_read_only_command(args, repo.as_read_only(), cwd),
     }
 }
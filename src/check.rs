@@ -0,0 +1,483 @@
+// Copyright (c) 2023 Richard Cook
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+use crate::data_dir_lock::DataDirLock;
+use crate::error::RepoError;
+use crate::link::LinkRecord;
+use crate::link_id::LinkId;
+use crate::manifest::ManifestRecord;
+use crate::meta_id::MetaId;
+use crate::read_only_repo::{ReadOnlyRepo, MANIFEST_FILE_NAME};
+use crate::result::RepoResult;
+use crate::schema::{from_versioned_value, SchemaKind};
+use joatmon::{read_yaml_file, safe_write_file};
+use serde_yaml::Value;
+use std::ffi::OsStr;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs::{read_dir, remove_file, rename};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheckProblemKind {
+    LinkFileNameMismatch,
+    LinkIdMismatch,
+    ManifestMetaIdMismatch,
+    MissingManifest,
+    UnparseableFile,
+    StrayFile,
+    TempFile,
+    OrphanedLockFile,
+}
+
+#[derive(Clone, Debug)]
+pub struct CheckProblem {
+    kind: CheckProblemKind,
+    path: PathBuf,
+    message: String,
+    repair: Option<Repair>,
+}
+
+impl CheckProblem {
+    #[must_use]
+    pub const fn kind(&self) -> CheckProblemKind {
+        self.kind
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    #[must_use]
+    pub const fn repair(&self) -> Option<&Repair> {
+        self.repair.as_ref()
+    }
+
+    fn new(
+        kind: CheckProblemKind,
+        path: &Path,
+        message: String,
+        repair: Option<RepairAction>,
+    ) -> Self {
+        Self {
+            kind,
+            path: path.to_path_buf(),
+            message,
+            repair: repair.map(Repair),
+        }
+    }
+}
+
+impl Display for CheckProblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Repair(RepairAction);
+
+#[derive(Clone, Debug)]
+enum RepairAction {
+    DeleteFile(PathBuf),
+    DeleteLockFile(PathBuf),
+    RenameFile(PathBuf, PathBuf),
+    MoveLink(PathBuf, PathBuf, Box<LinkRecord>),
+    RewriteManifest(PathBuf, Box<ManifestRecord>),
+}
+
+impl Repair {
+    pub(crate) fn apply(&self) -> RepoResult<()> {
+        match &self.0 {
+            RepairAction::DeleteFile(path) => {
+                remove_file(path).map_err(|_e| RepoError::could_not_delete_file(path))?;
+            }
+            RepairAction::DeleteLockFile(path) => {
+                // Only delete lock files that no process holds and whose
+                // data directory has not reappeared since the check
+                let data_dir = path.with_extension("");
+                let _lock = DataDirLock::try_acquire(&data_dir)?
+                    .ok_or_else(|| RepoError::could_not_lock(path))?;
+                if !data_dir.exists() {
+                    remove_file(path).map_err(|_e| RepoError::could_not_delete_file(path))?;
+                }
+            }
+            RepairAction::RenameFile(from, to) => {
+                rename(from, to).map_err(RepoError::other)?;
+            }
+            RepairAction::MoveLink(from, to, record) => {
+                let yaml_str = serde_yaml::to_string(record).map_err(RepoError::other)?;
+                safe_write_file(to, yaml_str, false).map_err(RepoError::other)?;
+                remove_file(from).map_err(|_e| RepoError::could_not_delete_file(from))?;
+            }
+            RepairAction::RewriteManifest(path, record) => {
+                let yaml_str = serde_yaml::to_string(record).map_err(RepoError::other)?;
+                safe_write_file(path, yaml_str, true).map_err(RepoError::other)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for Repair {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match &self.0 {
+            RepairAction::DeleteFile(path) => write!(f, "Delete file {}", path.display()),
+            RepairAction::DeleteLockFile(path) => {
+                write!(f, "Delete unused lock file {}", path.display())
+            }
+            RepairAction::RenameFile(from, to) => {
+                write!(f, "Rename file {} to {}", from.display(), to.display())
+            }
+            RepairAction::MoveLink(from, to, _) => write!(
+                f,
+                "Rewrite link file {} as {}",
+                from.display(),
+                to.display()
+            ),
+            RepairAction::RewriteManifest(path, record) => write!(
+                f,
+                "Rewrite manifest {} with meta ID {}",
+                path.display(),
+                record.meta_id
+            ),
+        }
+    }
+}
+
+pub fn check(repo: &ReadOnlyRepo) -> RepoResult<Vec<CheckProblem>> {
+    let mut problems = Vec::new();
+    check_links_dir(repo, &mut problems)?;
+    check_container_dir(repo, &mut problems)?;
+    Ok(problems)
+}
+
+fn check_links_dir(repo: &ReadOnlyRepo, problems: &mut Vec<CheckProblem>) -> RepoResult<()> {
+    let links_dir = repo.links_dir();
+    if !links_dir.is_dir() {
+        return Ok(());
+    }
+
+    for e in read_dir(links_dir).map_err(RepoError::other)? {
+        let e = e.map_err(RepoError::other)?;
+        let path = e.path();
+        let is_dir = e.file_type().map_err(RepoError::other)?.is_dir();
+        if !is_dir && is_temp_file_name(&e.file_name()) {
+            problems.push(temp_file(&path));
+        } else if is_dir || path.extension() != Some(OsStr::new("yaml")) {
+            problems.push(stray_file(&path, is_dir));
+        } else {
+            check_link_file(repo, &path, problems);
+        }
+    }
+
+    Ok(())
+}
+
+fn check_link_file(repo: &ReadOnlyRepo, link_path: &Path, problems: &mut Vec<CheckProblem>) {
+    let record = match read_yaml_file::<Value>(link_path)
+        .map_err(RepoError::other)
+        .and_then(|value| from_versioned_value::<LinkRecord>(SchemaKind::Link, link_path, value))
+    {
+        Ok(record) => record,
+        Err(e) => {
+            problems.push(unparseable_file(link_path, &e));
+            return;
+        }
+    };
+
+    let Ok(expected_link_id) = LinkId::compute(&record.project_dir, record.link_id.algorithm())
+    else {
+        return;
+    };
+
+    if record.link_id != expected_link_id {
+        let expected_link_path = repo.make_link_path(&expected_link_id);
+        let repair = (!expected_link_path.exists()).then(|| {
+            RepairAction::MoveLink(
+                link_path.to_path_buf(),
+                expected_link_path,
+                Box::new(LinkRecord {
+                    hash_algorithm: expected_link_id.algorithm(),
+                    link_id: expected_link_id.clone(),
+                    ..record.clone()
+                }),
+            )
+        });
+        problems.push(CheckProblem::new(
+            CheckProblemKind::LinkIdMismatch,
+            link_path,
+            format!(
+                "Link ID {} in link file {} does not match project directory {} (expected {})",
+                record.link_id,
+                link_path.display(),
+                record.project_dir.display(),
+                expected_link_id
+            ),
+            repair,
+        ));
+        return;
+    }
+
+    let expected_link_path = repo.make_link_path(&record.link_id);
+    if expected_link_path != link_path {
+        let repair = (!expected_link_path.exists())
+            .then(|| RepairAction::RenameFile(link_path.to_path_buf(), expected_link_path));
+        problems.push(CheckProblem::new(
+            CheckProblemKind::LinkFileNameMismatch,
+            link_path,
+            format!(
+                "Name of link file {} does not match link ID {}",
+                link_path.display(),
+                record.link_id
+            ),
+            repair,
+        ));
+    }
+}
+
+fn check_container_dir(repo: &ReadOnlyRepo, problems: &mut Vec<CheckProblem>) -> RepoResult<()> {
+    let container_dir = repo.container_dir();
+    if !container_dir.is_dir() {
+        return Ok(());
+    }
+
+    for e in read_dir(container_dir).map_err(RepoError::other)? {
+        let e = e.map_err(RepoError::other)?;
+        let path = e.path();
+        let file_name = e.file_name();
+        if e.file_type().map_err(RepoError::other)?.is_dir() {
+            match file_name.to_str().map(str::parse::<MetaId>) {
+                Some(Ok(meta_id)) => check_data_dir(&path, &meta_id, problems),
+                _ => problems.push(stray_file(&path, true)),
+            }
+        } else if is_temp_file_name(&file_name) {
+            problems.push(temp_file(&path));
+        } else if DataDirLock::is_lock_file(&path) {
+            if !path.with_extension("").is_dir() {
+                problems.push(CheckProblem::new(
+                    CheckProblemKind::OrphanedLockFile,
+                    &path,
+                    format!("Lock file {} has no data directory", path.display()),
+                    Some(RepairAction::DeleteLockFile(path.clone())),
+                ));
+            }
+        } else {
+            problems.push(stray_file(&path, false));
+        }
+    }
+
+    Ok(())
+}
+
+fn check_data_dir(data_dir: &Path, meta_id: &MetaId, problems: &mut Vec<CheckProblem>) {
+    let manifest_path = data_dir.join(MANIFEST_FILE_NAME);
+    if !manifest_path.is_file() {
+        problems.push(CheckProblem::new(
+            CheckProblemKind::MissingManifest,
+            data_dir,
+            format!("Data directory {} has no manifest", data_dir.display()),
+            None,
+        ));
+        return;
+    }
+
+    let record = match read_yaml_file::<Value>(&manifest_path)
+        .map_err(RepoError::other)
+        .and_then(|value| {
+            from_versioned_value::<ManifestRecord>(SchemaKind::Manifest, &manifest_path, value)
+        }) {
+        Ok(record) => record,
+        Err(e) => {
+            problems.push(unparseable_file(&manifest_path, &e));
+            return;
+        }
+    };
+
+    if record.meta_id != *meta_id {
+        problems.push(CheckProblem::new(
+            CheckProblemKind::ManifestMetaIdMismatch,
+            &manifest_path,
+            format!(
+                "Meta ID {} in manifest {} does not match data directory name",
+                record.meta_id,
+                manifest_path.display()
+            ),
+            Some(RepairAction::RewriteManifest(
+                manifest_path.clone(),
+                Box::new(ManifestRecord {
+                    meta_id: meta_id.clone(),
+                    ..record
+                }),
+            )),
+        ));
+    }
+}
+
+fn temp_file(path: &Path) -> CheckProblem {
+    CheckProblem::new(
+        CheckProblemKind::TempFile,
+        path,
+        format!("Leftover temporary file {}", path.display()),
+        Some(RepairAction::DeleteFile(path.to_path_buf())),
+    )
+}
+
+fn stray_file(path: &Path, is_dir: bool) -> CheckProblem {
+    CheckProblem::new(
+        CheckProblemKind::StrayFile,
+        path,
+        if is_dir {
+            format!("Unexpected directory {}", path.display())
+        } else {
+            format!("Unexpected file {}", path.display())
+        },
+        (!is_dir).then(|| RepairAction::DeleteFile(path.to_path_buf())),
+    )
+}
+
+fn unparseable_file(path: &Path, e: &RepoError) -> CheckProblem {
+    CheckProblem::new(
+        CheckProblemKind::UnparseableFile,
+        path,
+        format!("Could not parse file {}: {e}", path.display()),
+        None,
+    )
+}
+
+fn is_temp_file_name(file_name: &OsStr) -> bool {
+    let file_name = file_name.to_string_lossy();
+    file_name.ends_with(".tmp")
+        || file_name.ends_with('~')
+        || file_name.starts_with(".#")
+        || file_name.starts_with(".tmp")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CheckProblem, CheckProblemKind};
+    use crate::config::RepoConfig;
+    use crate::data_dir_lock::DataDirLock;
+    use crate::meta_id::MetaId;
+    use anyhow::Result;
+    use std::fs::{read_to_string, remove_dir_all, rename, write};
+    use tempdir::TempDir;
+
+    #[test]
+    fn check_basics() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let repo = RepoConfig::default(&temp_dir.path().join("repo"), None).repo_blocking()?;
+
+        let first = repo
+            .init(&temp_dir.path().join("first"))?
+            .expect("init must succeed");
+        let second = repo
+            .init(&temp_dir.path().join("second"))?
+            .expect("init must succeed");
+        let third = repo
+            .init(&temp_dir.path().join("third"))?
+            .expect("init must succeed");
+        assert!(repo.check()?.is_empty());
+
+        let renamed_link_path = repo.links_dir().join("renamed.yaml");
+        rename(first.link_path(), &renamed_link_path)?;
+
+        let link_yaml = read_to_string(second.link_path())?;
+        write(
+            second.link_path(),
+            link_yaml.replace("/second", "/second-moved"),
+        )?;
+
+        let manifest_yaml = read_to_string(third.manifest_path())?;
+        write(
+            third.manifest_path(),
+            manifest_yaml.replace(&third.meta_id().to_string(), &MetaId::random().to_string()),
+        )?;
+
+        write(repo.links_dir().join("stray.txt"), "")?;
+        write(repo.links_dir().join("link.yaml.tmp"), "")?;
+        write(repo.links_dir().join("bad.yaml"), "- not a link")?;
+        write(
+            repo.container_dir()
+                .join(format!("{}.lock", MetaId::random())),
+            "",
+        )?;
+
+        let problems = repo.check()?;
+        let mut kinds = problems.iter().map(CheckProblem::kind).collect::<Vec<_>>();
+        kinds.sort_by_key(|k| format!("{k:?}"));
+        assert_eq!(
+            vec![
+                CheckProblemKind::LinkFileNameMismatch,
+                CheckProblemKind::LinkIdMismatch,
+                CheckProblemKind::ManifestMetaIdMismatch,
+                CheckProblemKind::OrphanedLockFile,
+                CheckProblemKind::StrayFile,
+                CheckProblemKind::TempFile,
+                CheckProblemKind::UnparseableFile,
+            ],
+            kinds
+        );
+
+        assert_eq!(6, repo.repair(&problems)?);
+
+        let problems = repo.check()?;
+        assert_eq!(1, problems.len());
+        assert_eq!(CheckProblemKind::UnparseableFile, problems[0].kind());
+        assert!(problems[0].repair().is_none());
+
+        assert!(first.link_path().is_file());
+        assert!(repo.get(&temp_dir.path().join("second-moved"))?.is_some());
+        assert_eq!(
+            third.meta_id(),
+            repo.read_manifest(third.meta_id())?.meta_id()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn held_orphaned_lock_file_kept() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let repo = RepoConfig::default(&temp_dir.path().join("repo"), None).repo_blocking()?;
+        let dir_info = repo
+            .init(&temp_dir.path().join("project"))?
+            .expect("init must succeed");
+        let lock_path = DataDirLock::make_lock_path(dir_info.data_dir());
+        let lock = DataDirLock::try_acquire(dir_info.data_dir())?.expect("must lock");
+        remove_dir_all(dir_info.data_dir())?;
+
+        let problems = repo.check()?;
+        assert!(problems
+            .iter()
+            .any(|p| p.kind() == CheckProblemKind::OrphanedLockFile));
+        assert!(repo
+            .repair(&problems)
+            .expect_err("must fail")
+            .is_could_not_lock());
+        assert!(lock_path.is_file());
+
+        drop(lock);
+        let problems = repo.check()?;
+        repo.repair(&problems)?;
+        assert!(!lock_path.exists());
+        Ok(())
+    }
+}
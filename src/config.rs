@@ -19,20 +19,51 @@
 // OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
 // WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 //
+use crate::backup::restore_backup;
+use crate::config_loader::{merge_value, ConfigSource};
 use crate::error::RepoError;
+use crate::link_hash_algorithm::LinkHashAlgorithm;
+use crate::lock::{LockOwner, RepoLock};
+use crate::path_policy::PathPolicy;
+use crate::read_only_repo::ReadOnlyRepo;
 use crate::repo::Repo;
 use crate::result::RepoResult;
-use joatmon::{read_yaml_file, safe_write_file};
+use crate::schema::{read_versioned_value, SchemaKind, CURRENT_CONFIG_SCHEMA_VERSION};
+use crate::trash_policy::TrashPolicy;
+use joatmon::safe_write_file;
 use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use std::collections::BTreeMap;
+use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-#[derive(Debug, Deserialize, Serialize)]
+const QUARANTINE_DIR_KEY: &str = "quarantine_dir";
+const RELOCATED_TO_KEY: &str = "relocated_to";
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct RepoConfig {
+    #[serde(default)]
+    pub schema_version: u32,
     pub lock_path: PathBuf,
     pub config_path: PathBuf,
     pub links_dir: PathBuf,
     pub container_dir: PathBuf,
     pub shared_dir: PathBuf,
+    #[serde(default = "LinkHashAlgorithm::legacy")]
+    pub link_hash_algorithm: LinkHashAlgorithm,
+    #[serde(default = "PathPolicy::legacy")]
+    pub path_policy: PathPolicy,
+    #[serde(default)]
+    pub project_marker: Option<String>,
+    #[serde(default)]
+    pub quarantine_dir: Option<PathBuf>,
+    #[serde(default = "RepoConfig::default_quarantine_retention_days")]
+    pub quarantine_retention_days: u32,
+    #[serde(default)]
+    pub protected_prefixes: Vec<PathBuf>,
+    #[serde(default)]
+    pub trash_policy: TrashPolicy,
 }
 
 impl RepoConfig {
@@ -40,29 +71,198 @@ impl RepoConfig {
     pub fn default(base_dir: &Path, prefix: Option<&str>) -> Self {
         let full_prefix = prefix.map(|s| format!("{s}-")).unwrap_or_default();
         Self {
+            schema_version: CURRENT_CONFIG_SCHEMA_VERSION,
             lock_path: base_dir.join(format!(".{full_prefix}lock")),
             config_path: base_dir.join(format!("{full_prefix}config.yaml")),
             links_dir: base_dir.join(format!("{full_prefix}links")),
             container_dir: base_dir.join(format!("{full_prefix}data")),
             shared_dir: base_dir.join(format!("{full_prefix}shared")),
+            link_hash_algorithm: LinkHashAlgorithm::default(),
+            path_policy: PathPolicy::default(),
+            project_marker: None,
+            quarantine_dir: Some(base_dir.join(format!("{full_prefix}quarantine"))),
+            quarantine_retention_days: Self::default_quarantine_retention_days(),
+            protected_prefixes: Vec::new(),
+            trash_policy: TrashPolicy::default(),
         }
     }
 
+    const fn default_quarantine_retention_days() -> u32 {
+        7
+    }
+
     pub fn repo(self) -> RepoResult<Option<Repo>> {
-        Repo::new(if self.config_path.is_file() {
-            read_yaml_file::<Self>(&self.config_path).map_err(RepoError::other)?
+        Repo::new(self.load()?)
+    }
+
+    pub fn repo_blocking(self) -> RepoResult<Repo> {
+        Repo::new_blocking(self.load()?)
+    }
+
+    pub fn repo_with_timeout(self, timeout: Duration) -> RepoResult<Repo> {
+        Repo::new_with_timeout(self.load()?, timeout)
+    }
+
+    pub fn read_only_repo(self) -> RepoResult<Option<ReadOnlyRepo>> {
+        ReadOnlyRepo::new(self.load_read_only()?)
+    }
+
+    pub fn read_only_repo_blocking(self) -> RepoResult<ReadOnlyRepo> {
+        ReadOnlyRepo::new_blocking(self.load_read_only()?)
+    }
+
+    pub fn read_only_repo_with_timeout(self, timeout: Duration) -> RepoResult<ReadOnlyRepo> {
+        ReadOnlyRepo::new_with_timeout(self.load_read_only()?, timeout)
+    }
+
+    pub fn restore_from<R>(archive: R, base_dir: &Path) -> RepoResult<Self>
+    where
+        R: Read,
+    {
+        restore_backup(archive, base_dir)
+    }
+
+    pub fn lock_owner(&self) -> RepoResult<Option<LockOwner>> {
+        RepoLock::owner(&self.clone().load_read_only()?.lock_path)
+    }
+
+    pub fn break_lock(&self) -> RepoResult<bool> {
+        RepoLock::break_lock(&self.clone().load_read_only()?.lock_path)
+    }
+
+    pub(crate) fn base_dir(&self) -> &Path {
+        self.config_path.parent().unwrap_or_else(|| Path::new(""))
+    }
+
+    pub(crate) fn rebase(&self, new_base_dir: &Path) -> Self {
+        let old_base_dir = self.base_dir();
+        let rebase_path = |path: &Path| -> PathBuf {
+            if path.is_relative() {
+                new_base_dir.join(path)
+            } else {
+                path.strip_prefix(old_base_dir)
+                    .map_or_else(|_| path.to_path_buf(), |p| new_base_dir.join(p))
+            }
+        };
+
+        Self {
+            lock_path: rebase_path(&self.lock_path),
+            config_path: rebase_path(&self.config_path),
+            links_dir: rebase_path(&self.links_dir),
+            container_dir: rebase_path(&self.container_dir),
+            shared_dir: rebase_path(&self.shared_dir),
+            quarantine_dir: self.quarantine_dir.as_deref().map(rebase_path),
+            ..self.clone()
+        }
+    }
+
+    pub(crate) fn save(&self, overwrite: bool) -> RepoResult<()> {
+        let yaml_str = serde_yaml::to_string(&self.to_relative()).map_err(RepoError::other)?;
+        safe_write_file(&self.config_path, yaml_str, overwrite).map_err(RepoError::other)?;
+        Ok(())
+    }
+
+    // A relocated repository leaves a configuration file behind pointing at
+    // its new location so that opening the old location fails instead of
+    // creating an empty repository in its place
+    pub(crate) fn save_tombstone(&self, new_base_dir: &Path) -> RepoResult<()> {
+        let mut mapping = Mapping::new();
+        mapping.insert(
+            Value::from("schema_version"),
+            Value::from(CURRENT_CONFIG_SCHEMA_VERSION),
+        );
+        mapping.insert(
+            Value::from(RELOCATED_TO_KEY),
+            serde_yaml::to_value(new_base_dir).map_err(RepoError::other)?,
+        );
+        let yaml_str = serde_yaml::to_string(&mapping).map_err(RepoError::other)?;
+        safe_write_file(&self.config_path, yaml_str, true).map_err(RepoError::other)?;
+        Ok(())
+    }
+
+    pub(crate) fn read_repo_layer(config_path: &Path) -> RepoResult<Value> {
+        let value = read_versioned_value(SchemaKind::Config, config_path)?;
+        if let Some(new_base_dir) = value
+            .get(RELOCATED_TO_KEY)
+            .and_then(|v| serde_yaml::from_value::<PathBuf>(v.clone()).ok())
+        {
+            return Err(RepoError::repo_relocated(config_path, &new_base_dir));
+        }
+        Ok(value)
+    }
+
+    pub(crate) fn to_mapping(&self) -> RepoResult<Mapping> {
+        match serde_yaml::to_value(self).map_err(RepoError::other)? {
+            Value::Mapping(mapping) => Ok(mapping),
+            _ => unreachable!(),
+        }
+    }
+
+    fn to_relative(&self) -> Self {
+        let base_dir = self.base_dir();
+        let relative_path = |path: &Path| -> PathBuf {
+            path.strip_prefix(base_dir)
+                .map_or_else(|_| path.to_path_buf(), Path::to_path_buf)
+        };
+
+        Self {
+            lock_path: relative_path(&self.lock_path),
+            config_path: relative_path(&self.config_path),
+            links_dir: relative_path(&self.links_dir),
+            container_dir: relative_path(&self.container_dir),
+            shared_dir: relative_path(&self.shared_dir),
+            quarantine_dir: self.quarantine_dir.as_deref().map(relative_path),
+            ..self.clone()
+        }
+    }
+
+    fn load_read_only(self) -> RepoResult<Self> {
+        Ok(if self.config_path.is_file() {
+            self.read()?
         } else {
-            let yaml_str = serde_yaml::to_string(&self).map_err(RepoError::other)?;
-            safe_write_file(&self.config_path, yaml_str, false).map_err(RepoError::other)?;
             self
         })
     }
+
+    fn load(self) -> RepoResult<Self> {
+        Ok(if self.config_path.is_file() {
+            self.read()?
+        } else {
+            self.save(false)?;
+            self
+        })
+    }
+
+    // Quarantine is only enabled by default for new repositories: existing
+    // configuration files without a quarantine directory predate it
+    pub(crate) fn disable_new_repo_defaults(mapping: &mut Mapping) {
+        mapping.insert(Value::from(QUARANTINE_DIR_KEY), Value::Null);
+    }
+
+    fn read(&self) -> RepoResult<Self> {
+        let mut mapping = self.to_mapping()?;
+        Self::disable_new_repo_defaults(&mut mapping);
+        merge_value(
+            &mut mapping,
+            Self::read_repo_layer(&self.config_path)?,
+            &ConfigSource::Repo(self.config_path.clone()),
+            &mut BTreeMap::new(),
+        );
+        let mut config = serde_yaml::from_value::<Self>(Value::Mapping(mapping))
+            .map_err(|e| RepoError::invalid_file(&self.config_path, &e))?
+            .rebase(self.base_dir());
+        config.config_path.clone_from(&self.config_path);
+        Ok(config)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::lock::LockMode;
     use anyhow::Result;
+    use std::fs::{create_dir, read_to_string, remove_file, rename, write};
+    use std::process::id;
     use tempdir::TempDir;
 
     #[test]
@@ -74,6 +274,71 @@ mod tests {
         assert_eq!(base_dir.path().join("links"), c.links_dir);
         assert_eq!(base_dir.path().join("data"), c.container_dir);
         assert_eq!(base_dir.path().join("shared"), c.shared_dir);
+        assert_eq!(LinkHashAlgorithm::Sha256, c.link_hash_algorithm);
+        assert_eq!(PathPolicy::Lexical, c.path_policy);
+        assert_eq!(Some(base_dir.path().join("quarantine")), c.quarantine_dir);
+        Ok(())
+    }
+
+    #[test]
+    fn legacy_config() -> Result<()> {
+        let c = serde_yaml::from_str::<RepoConfig>(
+            "lock_path: /repo/.lock\n\
+             config_path: /repo/config.yaml\n\
+             links_dir: /repo/links\n\
+             container_dir: /repo/data\n\
+             shared_dir: /repo/shared\n",
+        )?;
+        assert_eq!(LinkHashAlgorithm::Md5, c.link_hash_algorithm);
+        assert_eq!(PathPolicy::Verbatim, c.path_policy);
+        assert_eq!(None, c.quarantine_dir);
+        assert!(c.trash_policy.is_default());
+        Ok(())
+    }
+
+    #[test]
+    fn quarantine_dir() -> Result<()> {
+        let base_dir = TempDir::new("joat-repo-test")?;
+        let config_path = base_dir.path().join("config.yaml");
+        let paths = "lock_path: .lock\n\
+                     config_path: config.yaml\n\
+                     links_dir: links\n\
+                     container_dir: data\n\
+                     shared_dir: shared\n";
+
+        write(&config_path, format!("schema_version: 1\n{paths}"))?;
+        let c = RepoConfig::default(base_dir.path(), None).load()?;
+        assert_eq!(None, c.quarantine_dir);
+
+        write(
+            &config_path,
+            format!("schema_version: 1\n{paths}quarantine_dir: quarantine\n"),
+        )?;
+        let c = RepoConfig::default(base_dir.path(), None).load()?;
+        assert_eq!(Some(base_dir.path().join("quarantine")), c.quarantine_dir);
+
+        remove_file(&config_path)?;
+        let c = RepoConfig::default(base_dir.path(), None).load()?;
+        assert_eq!(Some(base_dir.path().join("quarantine")), c.quarantine_dir);
+        assert!(read_to_string(&config_path)?.contains("quarantine_dir: quarantine\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn trash_policy_config() -> Result<()> {
+        let c = serde_yaml::from_str::<RepoConfig>(
+            "lock_path: /repo/.lock\n\
+             config_path: /repo/config.yaml\n\
+             links_dir: /repo/links\n\
+             container_dir: /repo/data\n\
+             shared_dir: /repo/shared\n\
+             trash_policy:\n  \
+               unreferenced_retention_days: 30\n  \
+               max_data_size: 1000000\n",
+        )?;
+        assert_eq!(Some(30), c.trash_policy.unreferenced_retention_days);
+        assert_eq!(None, c.trash_policy.link_idle_days);
+        assert_eq!(Some(1_000_000), c.trash_policy.max_data_size);
         Ok(())
     }
 
@@ -86,6 +351,163 @@ mod tests {
         assert_eq!(base_dir.path().join("foo-links"), c.links_dir);
         assert_eq!(base_dir.path().join("foo-data"), c.container_dir);
         assert_eq!(base_dir.path().join("foo-shared"), c.shared_dir);
+        assert_eq!(
+            Some(base_dir.path().join("foo-quarantine")),
+            c.quarantine_dir
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn relative_paths() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let project_dir = temp_dir.path().join("project");
+        create_dir(&project_dir)?;
+        let old_base_dir = temp_dir.path().join("old");
+        let new_base_dir = temp_dir.path().join("new");
+
+        let repo = RepoConfig::default(&old_base_dir, None).repo_blocking()?;
+        let meta_id = repo
+            .init(&project_dir)?
+            .expect("must succeed")
+            .meta_id()
+            .clone();
+        drop(repo);
+
+        let yaml_str = read_to_string(old_base_dir.join("config.yaml"))?;
+        assert!(yaml_str.contains("links_dir: links\n"));
+        assert!(yaml_str.contains("quarantine_dir: quarantine\n"));
+
+        rename(&old_base_dir, &new_base_dir)?;
+        let repo = RepoConfig::default(&new_base_dir, None).repo_blocking()?;
+        assert_eq!(new_base_dir.join("links"), repo.links_dir());
+        assert_eq!(new_base_dir.join(".lock"), repo.lock_path());
+        assert_eq!(
+            meta_id,
+            *repo.get(&project_dir)?.expect("must exist").meta_id()
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn moved_absolute_paths() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let base_dir = temp_dir.path().join("repo");
+        create_dir(&base_dir)?;
+        write(
+            base_dir.join("config.yaml"),
+            "lock_path: /old/repo/.lock\n\
+             config_path: /old/repo/config.yaml\n\
+             links_dir: /old/repo/links\n\
+             container_dir: /old/repo/data\n\
+             shared_dir: /elsewhere/shared\n",
+        )?;
+
+        let c = RepoConfig::default(&base_dir, None).load_read_only()?;
+        assert_eq!(base_dir.join(".lock"), c.lock_path);
+        assert_eq!(base_dir.join("config.yaml"), c.config_path);
+        assert_eq!(base_dir.join("links"), c.links_dir);
+        assert_eq!(base_dir.join("data"), c.container_dir);
+        assert_eq!(Path::new("/elsewhere/shared"), c.shared_dir);
         Ok(())
     }
+
+    #[test]
+    fn repo_with_timeout_basics() -> Result<()> {
+        let base_dir = TempDir::new("joat-repo-test")?;
+        let repo = RepoConfig::default(base_dir.path(), None).repo_with_timeout(TIMEOUT)?;
+        drop(repo);
+        assert!(RepoConfig::default(base_dir.path(), None)
+            .repo_with_timeout(TIMEOUT)
+            .is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn repo_with_timeout_locked() -> Result<()> {
+        let base_dir = TempDir::new("joat-repo-test")?;
+        let _repo = RepoConfig::default(base_dir.path(), None).repo_blocking()?;
+        let e = RepoConfig::default(base_dir.path(), None)
+            .repo_with_timeout(TIMEOUT)
+            .expect_err("lock should be held");
+        assert!(e.is_lock_timed_out());
+        assert!(RepoConfig::default(base_dir.path(), None).repo()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn read_only_repo_shared() -> Result<()> {
+        let base_dir = TempDir::new("joat-repo-test")?;
+        let _repo0 = RepoConfig::default(base_dir.path(), None).read_only_repo_blocking()?;
+        let _repo1 =
+            RepoConfig::default(base_dir.path(), None).read_only_repo_with_timeout(TIMEOUT)?;
+        assert!(RepoConfig::default(base_dir.path(), None).repo()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn read_only_repo_exclusive() -> Result<()> {
+        let base_dir = TempDir::new("joat-repo-test")?;
+        let repo = RepoConfig::default(base_dir.path(), None).repo_blocking()?;
+        assert!(RepoConfig::default(base_dir.path(), None)
+            .read_only_repo()?
+            .is_none());
+        drop(repo);
+        assert!(RepoConfig::default(base_dir.path(), None)
+            .read_only_repo()?
+            .is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn lock_owner_basics() -> Result<()> {
+        let base_dir = TempDir::new("joat-repo-test")?;
+        let config = RepoConfig::default(base_dir.path(), None);
+        assert!(config.lock_owner()?.is_none());
+
+        let repo = config.clone().repo_blocking()?;
+        let owner = config.lock_owner()?.expect("lock should be held");
+        assert_eq!(LockMode::Exclusive, owner.mode());
+        assert_eq!(Some(id()), owner.pid());
+        assert!(!owner.is_stale());
+        assert!(!config.break_lock()?);
+
+        drop(repo);
+        assert!(config.lock_owner()?.is_none());
+        assert!(!config.break_lock()?);
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn break_lock_stale() -> Result<()> {
+        let base_dir = TempDir::new("joat-repo-test")?;
+        let config = RepoConfig::default(base_dir.path(), None);
+        let repo = config.clone().repo_blocking()?;
+        let lock_contents = read_to_string(&config.lock_path)?;
+        let dead_contents =
+            lock_contents.replacen(&format!("{}", id()), &format!("{}", u32::MAX), 1);
+
+        // Without a host identity the owner's liveness cannot be confirmed
+        write(&config.lock_path, format!("{}", u32::MAX))?;
+        assert!(!config.break_lock()?);
+
+        write(&config.lock_path, &dead_contents)?;
+        assert!(config.break_lock()?);
+        assert!(config.clone().repo()?.is_some());
+        drop(repo);
+
+        // Readers record no owner so a leftover PID must not be trusted
+        let reader = config.clone().read_only_repo_blocking()?;
+        write(&config.lock_path, &dead_contents)?;
+        let owner = config.lock_owner()?.expect("lock should be held");
+        assert_eq!(LockMode::Shared, owner.mode());
+        assert!(!config.break_lock()?);
+        assert!(config.lock_path.is_file());
+        drop(reader);
+        Ok(())
+    }
+
+    const TIMEOUT: Duration = Duration::from_millis(100);
 }
@@ -0,0 +1,555 @@
+// Copyright (c) 2023 Richard Cook
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+use crate::config::RepoConfig;
+use crate::error::RepoError;
+use crate::read_only_repo::ReadOnlyRepo;
+use crate::repo::Repo;
+use crate::result::RepoResult;
+use crate::schema::CURRENT_CONFIG_SCHEMA_VERSION;
+use joatmon::read_yaml_file;
+use serde_yaml::{Mapping, Value};
+use std::collections::BTreeMap;
+use std::env::vars_os;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub const ENV_PREFIX: &str = "JOAT_REPO_";
+
+const ENV_KEY_SEPARATOR: &str = "__";
+const KEY_SEPARATOR: &str = ".";
+const CONFIG_PATH_KEY: &str = "config_path";
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConfigSource {
+    Default,
+    System(PathBuf),
+    Repo(PathBuf),
+    Environment(String),
+}
+
+impl Display for ConfigSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Default => write!(f, "default"),
+            Self::System(path) => write!(f, "system file {}", path.display()),
+            Self::Repo(path) => write!(f, "repository file {}", path.display()),
+            Self::Environment(name) => write!(f, "environment variable {name}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ConfigEntry {
+    key: String,
+    value: Value,
+    source: ConfigSource,
+}
+
+impl ConfigEntry {
+    #[must_use]
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    #[must_use]
+    pub const fn value(&self) -> &Value {
+        &self.value
+    }
+
+    #[must_use]
+    pub const fn source(&self) -> &ConfigSource {
+        &self.source
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ConfigLoader {
+    base_dir: PathBuf,
+    prefix: Option<String>,
+    system_config_path: Option<PathBuf>,
+}
+
+impl ConfigLoader {
+    #[must_use]
+    pub fn new(base_dir: &Path, prefix: Option<&str>) -> Self {
+        Self {
+            base_dir: base_dir.to_path_buf(),
+            prefix: prefix.map(String::from),
+            system_config_path: Self::default_system_config_path(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_system_config_path(mut self, path: Option<&Path>) -> Self {
+        self.system_config_path = path.map(Path::to_path_buf);
+        self
+    }
+
+    #[must_use]
+    pub fn default_system_config_path() -> Option<PathBuf> {
+        if cfg!(unix) {
+            Some(PathBuf::from("/etc/joat-repo/config.yaml"))
+        } else {
+            None
+        }
+    }
+
+    pub fn load(&self) -> RepoResult<LayeredConfig> {
+        self.load_with_env(
+            vars_os().filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?))),
+        )
+    }
+
+    pub fn load_with_env<I, K, V>(&self, vars: I) -> RepoResult<LayeredConfig>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let defaults = RepoConfig::default(&self.base_dir, self.prefix.as_deref());
+        let mut mapping = defaults.to_mapping()?;
+        if defaults.config_path.is_file() {
+            RepoConfig::disable_new_repo_defaults(&mut mapping);
+        }
+        let mut sources = BTreeMap::new();
+        record_sources(&mapping, "", &ConfigSource::Default, &mut sources);
+
+        if let Some(path) = self.system_config_path.as_ref().filter(|p| p.is_file()) {
+            let value = read_layer(path)?;
+            merge_value(
+                &mut mapping,
+                value,
+                &ConfigSource::System(path.clone()),
+                &mut sources,
+            );
+        }
+
+        if defaults.config_path.is_file() {
+            let value = RepoConfig::read_repo_layer(&defaults.config_path)?;
+            merge_value(
+                &mut mapping,
+                value,
+                &ConfigSource::Repo(defaults.config_path.clone()),
+                &mut sources,
+            );
+        }
+
+        let mut vars = vars
+            .into_iter()
+            .filter_map(|(k, v)| {
+                let key = k.as_ref().strip_prefix(ENV_PREFIX)?.to_lowercase();
+                let top_level_key = key.split(ENV_KEY_SEPARATOR).next()?;
+                mapping
+                    .contains_key(top_level_key)
+                    .then(|| (String::from(k.as_ref()), key, String::from(v.as_ref())))
+            })
+            .collect::<Vec<_>>();
+        vars.sort();
+        for (name, key, value) in vars {
+            let value = serde_yaml::from_str::<Value>(&value).unwrap_or(Value::String(value));
+            let overlay = key.rsplit(ENV_KEY_SEPARATOR).fold(value, |value, k| {
+                let mut m = Mapping::new();
+                m.insert(Value::from(k), value);
+                Value::Mapping(m)
+            });
+            merge_value(
+                &mut mapping,
+                overlay,
+                &ConfigSource::Environment(name),
+                &mut sources,
+            );
+        }
+
+        let mut config = serde_yaml::from_value::<RepoConfig>(Value::Mapping(mapping))
+            .map_err(RepoError::other)?
+            .rebase(defaults.base_dir());
+        config.config_path.clone_from(&defaults.config_path);
+        sources.insert(String::from(CONFIG_PATH_KEY), ConfigSource::Default);
+
+        let mut values = BTreeMap::new();
+        flatten(&config.to_mapping()?, "", &mut values);
+        let entries = values
+            .into_iter()
+            .map(|(key, value)| {
+                let source = sources.remove(&key).unwrap_or(ConfigSource::Default);
+                ConfigEntry { key, value, source }
+            })
+            .collect();
+
+        Ok(LayeredConfig { config, entries })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LayeredConfig {
+    config: RepoConfig,
+    entries: Vec<ConfigEntry>,
+}
+
+impl LayeredConfig {
+    #[must_use]
+    pub const fn config(&self) -> &RepoConfig {
+        &self.config
+    }
+
+    #[must_use]
+    pub fn into_config(self) -> RepoConfig {
+        self.config
+    }
+
+    #[must_use]
+    pub fn entries(&self) -> &[ConfigEntry] {
+        &self.entries
+    }
+
+    #[must_use]
+    pub fn source(&self, key: &str) -> Option<&ConfigSource> {
+        self.entries
+            .iter()
+            .find(|e| e.key == key)
+            .map(ConfigEntry::source)
+    }
+
+    pub fn repo(self) -> RepoResult<Option<Repo>> {
+        self.save_defaults()?;
+        Repo::new(self.config)
+    }
+
+    pub fn repo_blocking(self) -> RepoResult<Repo> {
+        self.save_defaults()?;
+        Repo::new_blocking(self.config)
+    }
+
+    pub fn repo_with_timeout(self, timeout: Duration) -> RepoResult<Repo> {
+        self.save_defaults()?;
+        Repo::new_with_timeout(self.config, timeout)
+    }
+
+    pub fn read_only_repo(self) -> RepoResult<Option<ReadOnlyRepo>> {
+        ReadOnlyRepo::new(self.config)
+    }
+
+    pub fn read_only_repo_blocking(self) -> RepoResult<ReadOnlyRepo> {
+        ReadOnlyRepo::new_blocking(self.config)
+    }
+
+    pub fn read_only_repo_with_timeout(self, timeout: Duration) -> RepoResult<ReadOnlyRepo> {
+        ReadOnlyRepo::new_with_timeout(self.config, timeout)
+    }
+
+    // The settings in effect when the repository is created are saved with
+    // it so that opening it without the system and environment layers sees
+    // the same path policy and link hash algorithm
+    fn save_defaults(&self) -> RepoResult<()> {
+        if !self.config.config_path.is_file() {
+            self.config.save(false)?;
+        }
+        Ok(())
+    }
+}
+
+fn read_layer(path: &Path) -> RepoResult<Value> {
+    let value = read_yaml_file::<Value>(path).map_err(|e| RepoError::invalid_file(path, &e))?;
+    let Value::Mapping(mut mapping) = value else {
+        return Err(RepoError::invalid_file(path, &"expected a mapping"));
+    };
+
+    if let Some(value) = mapping.remove(SCHEMA_VERSION_KEY) {
+        let schema_version = value
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| RepoError::invalid_file(path, &"invalid schema_version"))?;
+        if schema_version > CURRENT_CONFIG_SCHEMA_VERSION {
+            return Err(RepoError::unsupported_schema_version(
+                path,
+                schema_version,
+                CURRENT_CONFIG_SCHEMA_VERSION,
+            ));
+        }
+    }
+
+    Ok(Value::Mapping(mapping))
+}
+
+fn make_key(prefix: &str, key: &Value) -> Option<String> {
+    let key = key.as_str()?;
+    Some(if prefix.is_empty() {
+        String::from(key)
+    } else {
+        format!("{prefix}{KEY_SEPARATOR}{key}")
+    })
+}
+
+fn flatten(mapping: &Mapping, prefix: &str, values: &mut BTreeMap<String, Value>) {
+    for (k, v) in mapping {
+        let Some(key) = make_key(prefix, k) else {
+            continue;
+        };
+        if let Value::Mapping(m) = v {
+            flatten(m, &key, values);
+        } else {
+            values.insert(key, v.clone());
+        }
+    }
+}
+
+fn record_sources(
+    mapping: &Mapping,
+    prefix: &str,
+    source: &ConfigSource,
+    sources: &mut BTreeMap<String, ConfigSource>,
+) {
+    let mut values = BTreeMap::new();
+    flatten(mapping, prefix, &mut values);
+    for key in values.into_keys() {
+        sources.insert(key, source.clone());
+    }
+}
+
+pub fn merge_value(
+    mapping: &mut Mapping,
+    value: Value,
+    source: &ConfigSource,
+    sources: &mut BTreeMap<String, ConfigSource>,
+) {
+    if let Value::Mapping(overlay) = value {
+        merge(mapping, overlay, "", source, sources);
+    }
+}
+
+fn merge(
+    mapping: &mut Mapping,
+    overlay: Mapping,
+    prefix: &str,
+    source: &ConfigSource,
+    sources: &mut BTreeMap<String, ConfigSource>,
+) {
+    for (k, v) in overlay {
+        let Some(key) = make_key(prefix, &k) else {
+            continue;
+        };
+        match (mapping.get_mut(&k), v) {
+            (Some(Value::Mapping(existing)), Value::Mapping(m)) => {
+                merge(existing, m, &key, source, sources);
+            }
+            (_, v) => {
+                let nested_prefix = format!("{key}{KEY_SEPARATOR}");
+                sources.retain(|s, _| *s != key && !s.starts_with(&nested_prefix));
+                if let Value::Mapping(m) = &v {
+                    record_sources(m, &key, source, sources);
+                } else {
+                    sources.insert(key, source.clone());
+                }
+                mapping.insert(k, v);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConfigLoader, ConfigSource};
+    use crate::config::RepoConfig;
+    use crate::link_hash_algorithm::LinkHashAlgorithm;
+    use crate::path_policy::PathPolicy;
+    use anyhow::Result;
+    use std::fs::{create_dir_all, read_to_string, write};
+    use tempdir::TempDir;
+
+    #[test]
+    fn defaults() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let base_dir = temp_dir.path().join("repo");
+        let layered = ConfigLoader::new(&base_dir, None)
+            .with_system_config_path(None)
+            .load_with_env(Vec::<(String, String)>::new())?;
+        assert_eq!(base_dir.join("links"), layered.config().links_dir);
+        assert!(layered
+            .entries()
+            .iter()
+            .all(|e| *e.source() == ConfigSource::Default));
+        assert!(!base_dir.join("config.yaml").exists());
+
+        let repo = layered.repo_blocking()?;
+        assert!(repo.config_path().is_file());
+        Ok(())
+    }
+
+    #[test]
+    fn layers() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let base_dir = temp_dir.path().join("repo");
+        create_dir_all(&base_dir)?;
+        let system_config_path = temp_dir.path().join("system.yaml");
+        write(
+            &system_config_path,
+            "schema_version: 1\n\
+             project_marker: .system-marker\n\
+             path_policy: verbatim\n\
+             trash_policy:\n  \
+               max_data_size: 1000\n  \
+               link_idle_days: 10\n",
+        )?;
+        let repo_config_path = base_dir.join("config.yaml");
+        write(
+            &repo_config_path,
+            "schema_version: 1\n\
+             lock_path: .lock\n\
+             config_path: config.yaml\n\
+             links_dir: other-links\n\
+             container_dir: data\n\
+             shared_dir: shared\n\
+             project_marker: .repo-marker\n\
+             trash_policy:\n  \
+               link_idle_days: 20\n",
+        )?;
+
+        let layered = ConfigLoader::new(&base_dir, None)
+            .with_system_config_path(Some(&system_config_path))
+            .load_with_env([
+                ("JOAT_REPO_LINK_HASH_ALGORITHM", "blake3"),
+                ("JOAT_REPO_TRASH_POLICY__LINK_IDLE_DAYS", "30"),
+                ("JOAT_REPO_DIR", "/ignored"),
+                ("OTHER_LINKS_DIR", "/ignored"),
+            ])?;
+
+        let config = layered.config();
+        assert_eq!(base_dir.join("other-links"), config.links_dir);
+        assert_eq!(base_dir.join("config.yaml"), config.config_path);
+        assert_eq!(Some(".repo-marker"), config.project_marker.as_deref());
+        assert_eq!(PathPolicy::Verbatim, config.path_policy);
+        assert_eq!(LinkHashAlgorithm::Blake3, config.link_hash_algorithm);
+        assert_eq!(Some(1000), config.trash_policy.max_data_size);
+        assert_eq!(Some(30), config.trash_policy.link_idle_days);
+
+        let system = ConfigSource::System(system_config_path);
+        let repo = ConfigSource::Repo(repo_config_path);
+        assert_eq!(Some(&ConfigSource::Default), layered.source("config_path"));
+        assert_eq!(
+            Some(&ConfigSource::Default),
+            layered.source("quarantine_retention_days")
+        );
+        assert_eq!(Some(&repo), layered.source("links_dir"));
+        assert_eq!(Some(&repo), layered.source("project_marker"));
+        assert_eq!(Some(&system), layered.source("path_policy"));
+        assert_eq!(Some(&system), layered.source("trash_policy.max_data_size"));
+        assert_eq!(
+            Some(&ConfigSource::Environment(String::from(
+                "JOAT_REPO_LINK_HASH_ALGORITHM"
+            ))),
+            layered.source("link_hash_algorithm")
+        );
+        assert_eq!(
+            Some(&ConfigSource::Environment(String::from(
+                "JOAT_REPO_TRASH_POLICY__LINK_IDLE_DAYS"
+            ))),
+            layered.source("trash_policy.link_idle_days")
+        );
+        assert_eq!(None, layered.source("dir"));
+        Ok(())
+    }
+
+    #[test]
+    fn settings_saved_on_repo_creation() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let base_dir = temp_dir.path().join("repo");
+        let system_config_path = temp_dir.path().join("system.yaml");
+        write(
+            &system_config_path,
+            "schema_version: 1\n\
+             path_policy: verbatim\n\
+             link_hash_algorithm: blake3\n",
+        )?;
+        let loader =
+            ConfigLoader::new(&base_dir, None).with_system_config_path(Some(&system_config_path));
+
+        let layered = loader.load_with_env(Vec::<(String, String)>::new())?;
+        let system = ConfigSource::System(system_config_path);
+        assert_eq!(Some(&system), layered.source("path_policy"));
+        assert_eq!(Some(&system), layered.source("link_hash_algorithm"));
+        drop(layered.repo_blocking()?);
+
+        let yaml_str = read_to_string(base_dir.join("config.yaml"))?;
+        assert!(yaml_str.contains("links_dir: links\n"));
+        assert!(yaml_str.contains("path_policy: verbatim\n"));
+        assert!(yaml_str.contains("link_hash_algorithm: blake3\n"));
+
+        let layered = loader.load_with_env(Vec::<(String, String)>::new())?;
+        let repo_source = ConfigSource::Repo(base_dir.join("config.yaml"));
+        assert_eq!(Some(&repo_source), layered.source("path_policy"));
+        assert_eq!(PathPolicy::Verbatim, layered.config().path_policy);
+
+        let repo = RepoConfig::default(&base_dir, None).repo_blocking()?;
+        assert_eq!(PathPolicy::Verbatim, repo.path_policy());
+        assert_eq!(LinkHashAlgorithm::Blake3, repo.link_hash_algorithm());
+        Ok(())
+    }
+
+    #[test]
+    fn relocated() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let base_dir = temp_dir.path().join("repo");
+        let loader = ConfigLoader::new(&base_dir, None).with_system_config_path(None);
+        let repo = loader
+            .load_with_env(Vec::<(String, String)>::new())?
+            .repo_blocking()?;
+        repo.relocate(&temp_dir.path().join("new"))?;
+
+        assert!(loader
+            .load_with_env(Vec::<(String, String)>::new())
+            .expect_err("must fail")
+            .is_repo_relocated());
+        Ok(())
+    }
+
+    #[test]
+    fn unversioned_system_file() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let base_dir = temp_dir.path().join("repo");
+        let system_config_path = temp_dir.path().join("system.yaml");
+        write(&system_config_path, "project_marker: .marker\n")?;
+
+        let layered = ConfigLoader::new(&base_dir, None)
+            .with_system_config_path(Some(&system_config_path))
+            .load_with_env(Vec::<(String, String)>::new())?;
+        let config = layered.config();
+        assert_eq!(Some(".marker"), config.project_marker.as_deref());
+        assert_eq!(LinkHashAlgorithm::Sha256, config.link_hash_algorithm);
+        assert_eq!(PathPolicy::Lexical, config.path_policy);
+        assert_eq!(
+            Some(&ConfigSource::System(system_config_path)),
+            layered.source("project_marker")
+        );
+        assert_eq!(
+            Some(&ConfigSource::Default),
+            layered.source("link_hash_algorithm")
+        );
+        assert_eq!(Some(&ConfigSource::Default), layered.source("path_policy"));
+        assert_eq!(
+            Some(&ConfigSource::Default),
+            layered.source("schema_version")
+        );
+        Ok(())
+    }
+}
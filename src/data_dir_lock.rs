@@ -0,0 +1,181 @@
+// Copyright (c) 2023 Richard Cook
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+use crate::error::RepoError;
+use crate::lock::{LockMode, RepoLock};
+use crate::meta_id::MetaId;
+use crate::result::RepoResult;
+use std::ffi::OsStr;
+use std::fs::{read_dir, remove_dir_all, rename};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const LOCK_FILE_EXTENSION: &str = "lock";
+
+#[derive(Debug)]
+pub struct DataDirLock {
+    data_dir: PathBuf,
+    lock_path: PathBuf,
+    _lock: RepoLock,
+}
+
+impl DataDirLock {
+    pub(crate) fn try_acquire(data_dir: &Path) -> RepoResult<Option<Self>> {
+        let lock_path = Self::make_lock_path(data_dir);
+        let mut lock = RepoLock::open(&lock_path)?;
+        Ok(if lock.try_lock(LockMode::Exclusive)? {
+            Some(Self::new(data_dir, lock_path, lock))
+        } else {
+            None
+        })
+    }
+
+    pub(crate) fn acquire_blocking(data_dir: &Path) -> RepoResult<Self> {
+        let lock_path = Self::make_lock_path(data_dir);
+        let mut lock = RepoLock::open(&lock_path)?;
+        lock.lock(LockMode::Exclusive)?;
+        Ok(Self::new(data_dir, lock_path, lock))
+    }
+
+    pub(crate) fn acquire_with_timeout(data_dir: &Path, timeout: Duration) -> RepoResult<Self> {
+        let lock_path = Self::make_lock_path(data_dir);
+        let mut lock = RepoLock::open(&lock_path)?;
+        lock.lock_with_timeout(LockMode::Exclusive, timeout)?;
+        Ok(Self::new(data_dir, lock_path, lock))
+    }
+
+    pub(crate) fn acquire_all(container_dir: &Path) -> RepoResult<Vec<Self>> {
+        let mut locks = Vec::new();
+
+        if container_dir.is_dir() {
+            for entry_opt in read_dir(container_dir).map_err(RepoError::other)? {
+                let entry = entry_opt.map_err(RepoError::other)?;
+                if entry.path().is_dir() {
+                    locks.push(Self::try_acquire(&entry.path())?.ok_or_else(|| {
+                        RepoError::could_not_lock(&Self::make_lock_path(&entry.path()))
+                    })?);
+                }
+            }
+        }
+
+        Ok(locks)
+    }
+
+    pub(crate) fn remove_data_dir(data_dir: &Path) -> RepoResult<()> {
+        Self::dispose_data_dir(data_dir, || {
+            remove_dir_all(data_dir).map_err(|_e| RepoError::could_not_delete_directory(data_dir))
+        })
+    }
+
+    pub(crate) fn move_data_dir(data_dir: &Path, target_dir: &Path) -> RepoResult<()> {
+        Self::dispose_data_dir(data_dir, || {
+            rename(data_dir, target_dir).map_err(RepoError::other)
+        })
+    }
+
+    // The lock file is left in place: unlinking it would let a process
+    // that opened it before the unlink lock a different file from one
+    // that opens it afterwards. Lock files are removed when the repository
+    // is purged
+    fn dispose_data_dir<F>(data_dir: &Path, f: F) -> RepoResult<()>
+    where
+        F: FnOnce() -> RepoResult<()>,
+    {
+        let _lock = Self::try_acquire(data_dir)?
+            .ok_or_else(|| RepoError::could_not_lock(&Self::make_lock_path(data_dir)))?;
+        f()
+    }
+
+    pub(crate) fn is_lock_file(path: &Path) -> bool {
+        path.extension() == Some(OsStr::new(LOCK_FILE_EXTENSION))
+            && path
+                .file_stem()
+                .and_then(OsStr::to_str)
+                .is_some_and(|s| s.parse::<MetaId>().is_ok())
+    }
+
+    #[must_use]
+    pub fn data_dir(&self) -> &Path {
+        &self.data_dir
+    }
+
+    #[must_use]
+    pub fn lock_path(&self) -> &Path {
+        &self.lock_path
+    }
+
+    fn new(data_dir: &Path, lock_path: PathBuf, lock: RepoLock) -> Self {
+        Self {
+            data_dir: data_dir.to_path_buf(),
+            lock_path,
+            _lock: lock,
+        }
+    }
+
+    pub(crate) fn make_lock_path(data_dir: &Path) -> PathBuf {
+        data_dir.with_extension(LOCK_FILE_EXTENSION)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DataDirLock;
+    use anyhow::Result;
+    use std::fs::create_dir_all;
+    use tempdir::TempDir;
+
+    #[test]
+    fn try_acquire_basics() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let data_dir = temp_dir.path().join("data").join("foo");
+        let lock = DataDirLock::try_acquire(&data_dir)?.expect("lock should be acquired");
+        assert_eq!(
+            temp_dir.path().join("data").join("foo.lock"),
+            lock.lock_path()
+        );
+        assert!(DataDirLock::try_acquire(&data_dir)?.is_none());
+
+        let other_data_dir = temp_dir.path().join("data").join("bar");
+        assert!(DataDirLock::try_acquire(&other_data_dir)?.is_some());
+
+        drop(lock);
+        assert!(DataDirLock::try_acquire(&data_dir)?.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn remove_data_dir_locked() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let data_dir = temp_dir.path().join("data").join("foo");
+        create_dir_all(&data_dir)?;
+
+        let lock = DataDirLock::acquire_blocking(&data_dir)?;
+        assert!(DataDirLock::acquire_all(&temp_dir.path().join("data")).is_err());
+        assert!(DataDirLock::remove_data_dir(&data_dir).is_err());
+        assert!(data_dir.is_dir());
+
+        drop(lock);
+        DataDirLock::remove_data_dir(&data_dir)?;
+        assert!(!data_dir.exists());
+        assert!(DataDirLock::make_lock_path(&data_dir).is_file());
+        Ok(())
+    }
+}
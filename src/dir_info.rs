@@ -23,6 +23,7 @@ use crate::link::Link;
 use crate::link_id::LinkId;
 use crate::manifest::Manifest;
 use crate::meta_id::MetaId;
+use crate::metadata::Metadata;
 use chrono::{DateTime, Utc};
 use std::path::Path;
 
@@ -48,6 +49,26 @@ impl DirInfo {
         self.manifest.created_at()
     }
 
+    #[must_use]
+    pub const fn last_accessed_at(&self) -> Option<&DateTime<Utc>> {
+        self.manifest.last_accessed_at()
+    }
+
+    #[must_use]
+    pub const fn access_count(&self) -> u64 {
+        self.manifest.access_count()
+    }
+
+    #[must_use]
+    pub const fn manifest_metadata(&self) -> &Metadata {
+        self.manifest.metadata()
+    }
+
+    #[must_use]
+    pub const fn link_metadata(&self) -> &Metadata {
+        self.link.metadata()
+    }
+
     #[must_use]
     pub fn original_project_dir(&self) -> &Path {
         self.manifest.original_project_dir()
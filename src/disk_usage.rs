@@ -0,0 +1,189 @@
+// Copyright (c) 2023 Richard Cook
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+use crate::error::RepoError;
+use crate::manifest::Manifest;
+use crate::result::RepoResult;
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::fs::{read_dir, Metadata};
+use std::ops::{Add, AddAssign};
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DiskUsage {
+    bytes: u64,
+    files: u64,
+}
+
+impl DiskUsage {
+    #[must_use]
+    pub const fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    #[must_use]
+    pub const fn files(&self) -> u64 {
+        self.files
+    }
+}
+
+impl Add for DiskUsage {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self {
+            bytes: self.bytes + rhs.bytes,
+            files: self.files + rhs.files,
+        }
+    }
+}
+
+impl AddAssign for DiskUsage {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+#[derive(Debug)]
+pub struct ManifestUsage {
+    manifest: Manifest,
+    usage: DiskUsage,
+}
+
+impl ManifestUsage {
+    #[must_use]
+    pub const fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    #[must_use]
+    pub const fn usage(&self) -> DiskUsage {
+        self.usage
+    }
+}
+
+#[derive(Debug)]
+pub struct UsageReport {
+    manifests: Vec<ManifestUsage>,
+    shared: DiskUsage,
+}
+
+impl UsageReport {
+    pub(crate) fn compute(manifests: Vec<Manifest>, shared_dir: &Path) -> RepoResult<Self> {
+        let mut walker = DiskUsageWalker::default();
+        let mut manifests = manifests
+            .into_iter()
+            .map(|manifest| {
+                let usage = walker.walk(manifest.data_dir())?;
+                Ok(ManifestUsage { manifest, usage })
+            })
+            .collect::<RepoResult<Vec<_>>>()?;
+        manifests.sort_by_key(|m| Reverse(m.usage.bytes));
+        let shared = walker.walk(shared_dir)?;
+        Ok(Self { manifests, shared })
+    }
+
+    #[must_use]
+    pub fn manifests(&self) -> &[ManifestUsage] {
+        &self.manifests
+    }
+
+    #[must_use]
+    pub const fn shared(&self) -> DiskUsage {
+        self.shared
+    }
+
+    #[must_use]
+    pub fn total(&self) -> DiskUsage {
+        self.manifests
+            .iter()
+            .fold(self.shared, |total, m| total + m.usage)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DiskUsageWalker {
+    seen: HashSet<(u64, u64)>,
+}
+
+impl DiskUsageWalker {
+    pub(crate) fn walk(&mut self, dir: &Path) -> RepoResult<DiskUsage> {
+        let mut usage = DiskUsage::default();
+        if !dir.is_dir() {
+            return Ok(usage);
+        }
+
+        for e in read_dir(dir).map_err(RepoError::other)? {
+            let e = e.map_err(RepoError::other)?;
+            let metadata = e.metadata().map_err(RepoError::other)?;
+            if metadata.is_dir() {
+                usage += self.walk(&e.path())?;
+            } else if metadata.is_file() && self.is_first_link(&metadata) {
+                usage.bytes += metadata.len();
+                usage.files += 1;
+            }
+        }
+
+        Ok(usage)
+    }
+
+    #[cfg(unix)]
+    fn is_first_link(&mut self, metadata: &Metadata) -> bool {
+        use std::os::unix::fs::MetadataExt;
+
+        metadata.nlink() <= 1 || self.seen.insert((metadata.dev(), metadata.ino()))
+    }
+
+    #[cfg(not(unix))]
+    #[allow(clippy::unused_self)]
+    const fn is_first_link(&mut self, _metadata: &Metadata) -> bool {
+        true
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::DiskUsageWalker;
+    use anyhow::Result;
+    use std::fs::{create_dir_all, hard_link, write};
+    use tempdir::TempDir;
+
+    #[test]
+    fn walk_basics() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let dir = temp_dir.path().join("dir");
+        let sub_dir = dir.join("sub");
+        create_dir_all(&sub_dir)?;
+        write(dir.join("a.txt"), "hello")?;
+        write(sub_dir.join("b.txt"), "world!")?;
+        hard_link(dir.join("a.txt"), sub_dir.join("c.txt"))?;
+
+        let usage = DiskUsageWalker::default().walk(&dir)?;
+        assert_eq!(11, usage.bytes());
+        assert_eq!(2, usage.files());
+
+        let usage = DiskUsageWalker::default().walk(&temp_dir.path().join("missing"))?;
+        assert_eq!(0, usage.bytes());
+        assert_eq!(0, usage.files());
+        Ok(())
+    }
+}
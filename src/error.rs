@@ -23,10 +23,12 @@ use anyhow::Error as AnyhowError;
 use joatmon::HasOtherError;
 use std::error::Error as StdError;
 use std::fmt::{Debug, Display};
+use std::io::Error as IOError;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use thiserror::Error;
 
-use crate::SharedPath;
+use crate::{MetaId, SharedPath};
 
 #[allow(unused)]
 #[derive(Debug, PartialEq)]
@@ -34,13 +36,28 @@ use crate::SharedPath;
 pub enum RepoErrorKind {
     CouldNotOpenLockFile,
     CouldNotLock,
+    LockTimedOut,
     InvalidSharedPath,
     CouldNotComputeHash,
     CouldNotDeleteDirectory,
     CouldNotDeleteFile,
+    CouldNotResolvePath,
     InvalidLinkFile,
     InvalidMetaId,
     InvalidLinkId,
+    MetaRefNotFound,
+    AmbiguousMetaRef,
+    InvalidMetaName,
+    DuplicateMetaName,
+    UnsupportedSchemaVersion,
+    InvalidFile,
+    DuplicateMetaId,
+    InvalidArchive,
+    DirectoryNotEmpty,
+    JournalFailed,
+    ProjectCopied,
+    AmbiguousMove,
+    RepoRelocated,
     Other,
 }
 
@@ -54,14 +71,18 @@ enum RepoErrorImpl {
     CouldNotOpenLockFile(PathBuf),
     #[error("Could not lock lock file {0}")]
     CouldNotLock(PathBuf),
+    #[error("Timed out after {1:?} waiting to lock lock file {0}")]
+    LockTimedOut(PathBuf, Duration),
     #[error("Invalid shared path {0}")]
     InvalidSharedPath(SharedPath),
-    #[error("Could not compute MD5 hash for path {0}")]
+    #[error("Could not compute hash for path {0}")]
     CouldNotComputeHash(PathBuf),
     #[error("Could not delete directory {0}")]
     CouldNotDeleteDirectory(PathBuf),
     #[error("Could not delete file {0}")]
     CouldNotDeleteFile(PathBuf),
+    #[error("Could not resolve path {0}: {1}")]
+    CouldNotResolvePath(PathBuf, String),
     #[error(
         "Project directory {1} specified in link file {0} does not match expected directory {2}"
     )]
@@ -70,6 +91,32 @@ enum RepoErrorImpl {
     InvalidMetaId(String),
     #[error("Invalid link ID {0}")]
     InvalidLinkId(String),
+    #[error("No metadirectory matches reference {0}")]
+    MetaRefNotFound(String),
+    #[error("Reference {0} is ambiguous and matches metadirectories {1}")]
+    AmbiguousMetaRef(String, String),
+    #[error("Invalid metadirectory name {0}")]
+    InvalidMetaName(String),
+    #[error("Name {0} is already used by metadirectory {1}")]
+    DuplicateMetaName(String, MetaId),
+    #[error("File {0} has schema version {1} which is newer than supported version {2}")]
+    UnsupportedSchemaVersion(PathBuf, u32, u32),
+    #[error("Could not parse file {0}: {1}")]
+    InvalidFile(PathBuf, String),
+    #[error("Metadirectory {0} already exists")]
+    DuplicateMetaId(MetaId),
+    #[error("Invalid archive: {0}")]
+    InvalidArchive(String),
+    #[error("Directory {0} is not empty")]
+    DirectoryNotEmpty(PathBuf),
+    #[error("Journal operation failed and was moved to {0}: {1}")]
+    JournalFailed(PathBuf, String),
+    #[error("Project directory {0} is a copy of a project using metadirectory {1}")]
+    ProjectCopied(PathBuf, MetaId),
+    #[error("Project directory {0} could have been moved from any of {1}")]
+    AmbiguousMove(PathBuf, String),
+    #[error("Repository configuration {0} has been relocated to {1}")]
+    RepoRelocated(PathBuf, PathBuf),
     #[error(transparent)]
     Other(AnyhowError),
 }
@@ -81,13 +128,30 @@ impl RepoError {
         match self.0 {
             RepoErrorImpl::CouldNotOpenLockFile(_) => RepoErrorKind::CouldNotOpenLockFile,
             RepoErrorImpl::CouldNotLock(_) => RepoErrorKind::CouldNotLock,
+            RepoErrorImpl::LockTimedOut(_, _) => RepoErrorKind::LockTimedOut,
             RepoErrorImpl::InvalidSharedPath(_) => RepoErrorKind::InvalidSharedPath,
             RepoErrorImpl::CouldNotComputeHash(_) => RepoErrorKind::CouldNotComputeHash,
             RepoErrorImpl::CouldNotDeleteDirectory(_) => RepoErrorKind::CouldNotDeleteDirectory,
             RepoErrorImpl::CouldNotDeleteFile(_) => RepoErrorKind::CouldNotDeleteFile,
+            RepoErrorImpl::CouldNotResolvePath(_, _) => RepoErrorKind::CouldNotResolvePath,
             RepoErrorImpl::InvalidLinkFile(_, _, _) => RepoErrorKind::InvalidLinkFile,
             RepoErrorImpl::InvalidMetaId(_) => RepoErrorKind::InvalidMetaId,
             RepoErrorImpl::InvalidLinkId(_) => RepoErrorKind::InvalidLinkId,
+            RepoErrorImpl::MetaRefNotFound(_) => RepoErrorKind::MetaRefNotFound,
+            RepoErrorImpl::AmbiguousMetaRef(_, _) => RepoErrorKind::AmbiguousMetaRef,
+            RepoErrorImpl::InvalidMetaName(_) => RepoErrorKind::InvalidMetaName,
+            RepoErrorImpl::DuplicateMetaName(_, _) => RepoErrorKind::DuplicateMetaName,
+            RepoErrorImpl::UnsupportedSchemaVersion(_, _, _) => {
+                RepoErrorKind::UnsupportedSchemaVersion
+            }
+            RepoErrorImpl::InvalidFile(_, _) => RepoErrorKind::InvalidFile,
+            RepoErrorImpl::DuplicateMetaId(_) => RepoErrorKind::DuplicateMetaId,
+            RepoErrorImpl::InvalidArchive(_) => RepoErrorKind::InvalidArchive,
+            RepoErrorImpl::DirectoryNotEmpty(_) => RepoErrorKind::DirectoryNotEmpty,
+            RepoErrorImpl::JournalFailed(_, _) => RepoErrorKind::JournalFailed,
+            RepoErrorImpl::ProjectCopied(_, _) => RepoErrorKind::ProjectCopied,
+            RepoErrorImpl::AmbiguousMove(_, _) => RepoErrorKind::AmbiguousMove,
+            RepoErrorImpl::RepoRelocated(_, _) => RepoErrorKind::RepoRelocated,
             _ => RepoErrorKind::Other,
         }
     }
@@ -104,6 +168,12 @@ impl RepoError {
         self.kind() == RepoErrorKind::CouldNotLock
     }
 
+    #[allow(unused)]
+    #[must_use]
+    pub fn is_lock_timed_out(&self) -> bool {
+        self.kind() == RepoErrorKind::LockTimedOut
+    }
+
     #[allow(unused)]
     #[must_use]
     pub fn is_invalid_shared_path(&self) -> bool {
@@ -128,6 +198,12 @@ impl RepoError {
         self.kind() == RepoErrorKind::CouldNotDeleteFile
     }
 
+    #[allow(unused)]
+    #[must_use]
+    pub fn is_could_not_resolve_path(&self) -> bool {
+        self.kind() == RepoErrorKind::CouldNotResolvePath
+    }
+
     #[allow(unused)]
     #[must_use]
     pub fn is_invalid_link_file(&self) -> bool {
@@ -146,6 +222,84 @@ impl RepoError {
         self.kind() == RepoErrorKind::InvalidLinkId
     }
 
+    #[allow(unused)]
+    #[must_use]
+    pub fn is_meta_ref_not_found(&self) -> bool {
+        self.kind() == RepoErrorKind::MetaRefNotFound
+    }
+
+    #[allow(unused)]
+    #[must_use]
+    pub fn is_ambiguous_meta_ref(&self) -> bool {
+        self.kind() == RepoErrorKind::AmbiguousMetaRef
+    }
+
+    #[allow(unused)]
+    #[must_use]
+    pub fn is_invalid_meta_name(&self) -> bool {
+        self.kind() == RepoErrorKind::InvalidMetaName
+    }
+
+    #[allow(unused)]
+    #[must_use]
+    pub fn is_duplicate_meta_name(&self) -> bool {
+        self.kind() == RepoErrorKind::DuplicateMetaName
+    }
+
+    #[allow(unused)]
+    #[must_use]
+    pub fn is_unsupported_schema_version(&self) -> bool {
+        self.kind() == RepoErrorKind::UnsupportedSchemaVersion
+    }
+
+    #[allow(unused)]
+    #[must_use]
+    pub fn is_invalid_file(&self) -> bool {
+        self.kind() == RepoErrorKind::InvalidFile
+    }
+
+    #[allow(unused)]
+    #[must_use]
+    pub fn is_duplicate_meta_id(&self) -> bool {
+        self.kind() == RepoErrorKind::DuplicateMetaId
+    }
+
+    #[allow(unused)]
+    #[must_use]
+    pub fn is_invalid_archive(&self) -> bool {
+        self.kind() == RepoErrorKind::InvalidArchive
+    }
+
+    #[allow(unused)]
+    #[must_use]
+    pub fn is_directory_not_empty(&self) -> bool {
+        self.kind() == RepoErrorKind::DirectoryNotEmpty
+    }
+
+    #[allow(unused)]
+    #[must_use]
+    pub fn is_journal_failed(&self) -> bool {
+        self.kind() == RepoErrorKind::JournalFailed
+    }
+
+    #[allow(unused)]
+    #[must_use]
+    pub fn is_project_copied(&self) -> bool {
+        self.kind() == RepoErrorKind::ProjectCopied
+    }
+
+    #[allow(unused)]
+    #[must_use]
+    pub fn is_ambiguous_move(&self) -> bool {
+        self.kind() == RepoErrorKind::AmbiguousMove
+    }
+
+    #[allow(unused)]
+    #[must_use]
+    pub fn is_repo_relocated(&self) -> bool {
+        self.kind() == RepoErrorKind::RepoRelocated
+    }
+
     #[allow(unused)]
     #[must_use]
     pub fn is_other(&self) -> bool {
@@ -160,6 +314,10 @@ impl RepoError {
         Self(RepoErrorImpl::CouldNotLock(path.to_path_buf()))
     }
 
+    pub(crate) fn lock_timed_out(path: &Path, timeout: Duration) -> Self {
+        Self(RepoErrorImpl::LockTimedOut(path.to_path_buf(), timeout))
+    }
+
     pub(crate) fn invalid_shared_path(shared_path: &SharedPath) -> Self {
         Self(RepoErrorImpl::InvalidSharedPath(shared_path.clone()))
     }
@@ -178,6 +336,13 @@ impl RepoError {
         Self(RepoErrorImpl::CouldNotDeleteFile(path.to_path_buf()))
     }
 
+    pub(crate) fn could_not_resolve_path(path: &Path, e: &IOError) -> Self {
+        Self(RepoErrorImpl::CouldNotResolvePath(
+            path.to_path_buf(),
+            e.to_string(),
+        ))
+    }
+
     pub(crate) fn invalid_link_file(
         link_path: &Path,
         project_dir: &Path,
@@ -198,6 +363,98 @@ impl RepoError {
         Self(RepoErrorImpl::InvalidLinkId(String::from(s)))
     }
 
+    pub(crate) fn meta_ref_not_found(s: &str) -> Self {
+        Self(RepoErrorImpl::MetaRefNotFound(String::from(s)))
+    }
+
+    pub(crate) fn ambiguous_meta_ref(s: &str, meta_ids: &[MetaId]) -> Self {
+        Self(RepoErrorImpl::AmbiguousMetaRef(
+            String::from(s),
+            meta_ids
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", "),
+        ))
+    }
+
+    pub(crate) fn invalid_meta_name(s: &str) -> Self {
+        Self(RepoErrorImpl::InvalidMetaName(String::from(s)))
+    }
+
+    pub(crate) fn duplicate_meta_name(s: &str, meta_id: &MetaId) -> Self {
+        Self(RepoErrorImpl::DuplicateMetaName(
+            String::from(s),
+            meta_id.clone(),
+        ))
+    }
+
+    pub(crate) fn unsupported_schema_version(
+        path: &Path,
+        schema_version: u32,
+        supported_schema_version: u32,
+    ) -> Self {
+        Self(RepoErrorImpl::UnsupportedSchemaVersion(
+            path.to_path_buf(),
+            schema_version,
+            supported_schema_version,
+        ))
+    }
+
+    pub(crate) fn invalid_file<E>(path: &Path, e: &E) -> Self
+    where
+        E: Display,
+    {
+        Self(RepoErrorImpl::InvalidFile(
+            path.to_path_buf(),
+            e.to_string(),
+        ))
+    }
+
+    pub(crate) fn duplicate_meta_id(meta_id: &MetaId) -> Self {
+        Self(RepoErrorImpl::DuplicateMetaId(meta_id.clone()))
+    }
+
+    pub(crate) fn invalid_archive(message: &str) -> Self {
+        Self(RepoErrorImpl::InvalidArchive(String::from(message)))
+    }
+
+    pub(crate) fn directory_not_empty(path: &Path) -> Self {
+        Self(RepoErrorImpl::DirectoryNotEmpty(path.to_path_buf()))
+    }
+
+    pub(crate) fn journal_failed(path: &Path, e: &Self) -> Self {
+        Self(RepoErrorImpl::JournalFailed(
+            path.to_path_buf(),
+            e.to_string(),
+        ))
+    }
+
+    pub(crate) fn project_copied(project_dir: &Path, meta_id: &MetaId) -> Self {
+        Self(RepoErrorImpl::ProjectCopied(
+            project_dir.to_path_buf(),
+            meta_id.clone(),
+        ))
+    }
+
+    pub(crate) fn ambiguous_move(project_dir: &Path, previous_project_dirs: &[&Path]) -> Self {
+        Self(RepoErrorImpl::AmbiguousMove(
+            project_dir.to_path_buf(),
+            previous_project_dirs
+                .iter()
+                .map(|p| p.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+        ))
+    }
+
+    pub(crate) fn repo_relocated(config_path: &Path, new_base_dir: &Path) -> Self {
+        Self(RepoErrorImpl::RepoRelocated(
+            config_path.to_path_buf(),
+            new_base_dir.to_path_buf(),
+        ))
+    }
+
     pub(crate) fn other<E>(e: E) -> Self
     where
         E: StdError + Send + Sync + 'static,
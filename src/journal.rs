@@ -0,0 +1,618 @@
+// Copyright (c) 2023 Richard Cook
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+use crate::archive::merge_dir;
+use crate::data_dir_lock::DataDirLock;
+use crate::error::RepoError;
+use crate::quarantine::Quarantine;
+use crate::result::RepoResult;
+use chrono::{DateTime, Utc};
+use joatmon::{read_yaml_file, safe_write_file};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fs::{
+    create_dir_all, read_dir, read_to_string, remove_dir_all, remove_file, rename, File,
+};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum JournalAction {
+    WriteFile {
+        #[serde(with = "crate::path_serde")]
+        path: PathBuf,
+        contents: String,
+    },
+    RemoveFile {
+        #[serde(with = "crate::path_serde")]
+        path: PathBuf,
+    },
+    WriteMarker {
+        #[serde(with = "crate::path_serde")]
+        path: PathBuf,
+        contents: String,
+    },
+    RemoveMarker {
+        #[serde(with = "crate::path_serde")]
+        path: PathBuf,
+    },
+    MoveDir {
+        #[serde(with = "crate::path_serde")]
+        from: PathBuf,
+        #[serde(with = "crate::path_serde")]
+        to: PathBuf,
+    },
+    MergeDir {
+        #[serde(with = "crate::path_serde")]
+        from: PathBuf,
+        #[serde(with = "crate::path_serde")]
+        to: PathBuf,
+    },
+    RemoveDataDir {
+        #[serde(with = "crate::path_serde")]
+        data_dir: PathBuf,
+    },
+    QuarantineDataDir {
+        #[serde(with = "crate::path_serde")]
+        data_dir: PathBuf,
+        #[serde(with = "crate::path_serde")]
+        entry_dir: PathBuf,
+        record: String,
+    },
+    MoveFiles {
+        #[serde(with = "crate::path_serde")]
+        from: PathBuf,
+        #[serde(with = "crate::path_serde")]
+        to: PathBuf,
+        #[serde(with = "crate::path_serde::vec")]
+        paths: Vec<PathBuf>,
+    },
+    RemoveDir {
+        #[serde(with = "crate::path_serde")]
+        path: PathBuf,
+    },
+    RestoreDataDir {
+        #[serde(with = "crate::path_serde")]
+        entry_dir: PathBuf,
+        #[serde(with = "crate::path_serde")]
+        data_dir: PathBuf,
+    },
+}
+
+impl JournalAction {
+    // Describes how to reverse this action given the current state of the
+    // file system: it must therefore be computed before the action runs
+    fn undo(&self) -> RepoResult<Option<Self>> {
+        Ok(match self {
+            Self::WriteFile { path, .. } => Some(match Self::read_existing(path)? {
+                Some(contents) => Self::WriteFile {
+                    path: path.clone(),
+                    contents,
+                },
+                None => match Self::first_missing_dir(path) {
+                    Some(dir) => Self::RemoveDir { path: dir },
+                    None => Self::RemoveFile { path: path.clone() },
+                },
+            }),
+            Self::RemoveFile { path } => {
+                Self::read_existing(path)?.map(|contents| Self::WriteFile {
+                    path: path.clone(),
+                    contents,
+                })
+            }
+            Self::WriteMarker { path, .. } => Some(match Self::read_existing(path)? {
+                Some(contents) => Self::WriteMarker {
+                    path: path.clone(),
+                    contents,
+                },
+                None => Self::RemoveMarker { path: path.clone() },
+            }),
+            Self::RemoveMarker { path } => {
+                Self::read_existing(path)?.map(|contents| Self::WriteMarker {
+                    path: path.clone(),
+                    contents,
+                })
+            }
+            Self::MoveDir { from, to } => (from.is_dir() && !to.exists()).then(|| Self::MoveDir {
+                from: to.clone(),
+                to: from.clone(),
+            }),
+            Self::MergeDir { from, to } => {
+                let mut paths = Vec::new();
+                Self::list_merged_files(from, to, Path::new(""), &mut paths)?;
+                Some(Self::MoveFiles {
+                    from: to.clone(),
+                    to: from.clone(),
+                    paths,
+                })
+            }
+            Self::QuarantineDataDir {
+                data_dir,
+                entry_dir,
+                ..
+            } => Some(Self::RestoreDataDir {
+                entry_dir: entry_dir.clone(),
+                data_dir: data_dir.clone(),
+            }),
+            Self::RemoveDataDir { .. }
+            | Self::MoveFiles { .. }
+            | Self::RemoveDir { .. }
+            | Self::RestoreDataDir { .. } => None,
+        })
+    }
+
+    fn apply(&self) -> RepoResult<()> {
+        match self {
+            Self::WriteFile { path, contents } => {
+                if let Some(parent) = path.parent() {
+                    create_dir_all(parent).map_err(RepoError::other)?;
+                }
+                safe_write_file(path, contents, true).map_err(RepoError::other)?;
+            }
+            Self::RemoveFile { path } => {
+                if path.is_file() {
+                    remove_file(path).map_err(|_e| RepoError::could_not_delete_file(path))?;
+                }
+            }
+            // Project markers are a convenience only: never recreate a
+            // project directory that has gone away and never fail the
+            // operation because a marker could not be updated
+            Self::WriteMarker { path, contents } => {
+                if path.parent().is_some_and(Path::is_dir) {
+                    _ = safe_write_file(path, contents, true);
+                }
+            }
+            Self::RemoveMarker { path } => {
+                if path.is_file() {
+                    _ = remove_file(path);
+                }
+            }
+            Self::MoveDir { from, to } => {
+                if from.is_dir() && !to.exists() {
+                    rename(from, to).map_err(RepoError::other)?;
+                }
+            }
+            Self::MergeDir { from, to } => merge_dir(from, to)?,
+            Self::RemoveDataDir { data_dir } => {
+                if data_dir.is_dir() {
+                    DataDirLock::remove_data_dir(data_dir)?;
+                }
+            }
+            Self::QuarantineDataDir {
+                data_dir,
+                entry_dir,
+                record,
+            } => Quarantine::store(data_dir, entry_dir, record)?,
+            Self::MoveFiles { from, to, paths } => {
+                for path in paths {
+                    let from_path = from.join(path);
+                    let to_path = to.join(path);
+                    if from_path.is_file() && !to_path.exists() {
+                        if let Some(parent) = to_path.parent() {
+                            create_dir_all(parent).map_err(RepoError::other)?;
+                        }
+                        rename(&from_path, &to_path).map_err(RepoError::other)?;
+                    }
+                }
+            }
+            Self::RemoveDir { path } => {
+                if path.is_dir() {
+                    remove_dir_all(path)
+                        .map_err(|_e| RepoError::could_not_delete_directory(path))?;
+                }
+            }
+            Self::RestoreDataDir {
+                entry_dir,
+                data_dir,
+            } => Quarantine::unstore(entry_dir, data_dir)?,
+        }
+        Ok(())
+    }
+
+    fn read_existing(path: &Path) -> RepoResult<Option<String>> {
+        if path.is_file() {
+            Ok(Some(read_to_string(path).map_err(RepoError::other)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn first_missing_dir(path: &Path) -> Option<PathBuf> {
+        let mut missing = None;
+        let mut dir = path.parent();
+        while let Some(d) = dir {
+            if d.as_os_str().is_empty() || d.exists() {
+                break;
+            }
+            missing = Some(d.to_path_buf());
+            dir = d.parent();
+        }
+        missing
+    }
+
+    fn list_merged_files(
+        from_dir: &Path,
+        to_dir: &Path,
+        rel: &Path,
+        paths: &mut Vec<PathBuf>,
+    ) -> RepoResult<()> {
+        let dir = from_dir.join(rel);
+        if !dir.is_dir() {
+            return Ok(());
+        }
+
+        for e in read_dir(&dir).map_err(RepoError::other)? {
+            let e = e.map_err(RepoError::other)?;
+            let rel_path = rel.join(e.file_name());
+            if e.file_type().map_err(RepoError::other)?.is_dir() {
+                Self::list_merged_files(from_dir, to_dir, &rel_path, paths)?;
+            } else if !to_dir.join(&rel_path).exists() {
+                paths.push(rel_path);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct JournalStep {
+    #[serde(flatten)]
+    action: JournalAction,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    undo: Option<JournalAction>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct JournalRecord {
+    operation: String,
+    started_at: DateTime<Utc>,
+    actions: Vec<JournalStep>,
+}
+
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    temp_path: PathBuf,
+    failed_path: PathBuf,
+}
+
+impl Journal {
+    pub(crate) fn new(config_path: &Path) -> Self {
+        Self {
+            path: config_path.with_extension("journal"),
+            temp_path: config_path.with_extension("journal.tmp"),
+            failed_path: config_path.with_extension("journal.failed"),
+        }
+    }
+
+    pub(crate) fn run(&self, operation: &str, actions: Vec<JournalAction>) -> RepoResult<()> {
+        let actions = actions
+            .into_iter()
+            .map(|action| {
+                let undo = action.undo()?;
+                Ok(JournalStep { action, undo })
+            })
+            .collect::<RepoResult<Vec<_>>>()?;
+        let record = JournalRecord {
+            operation: String::from(operation),
+            started_at: Utc::now(),
+            actions,
+        };
+        self.write(&record)?;
+        self.apply(&record, false)?;
+        self.finish()
+    }
+
+    pub(crate) fn replay(&self) -> RepoResult<bool> {
+        // A journal that was never committed has not been acted upon
+        if self.temp_path.is_file() {
+            remove_file(&self.temp_path)
+                .map_err(|_e| RepoError::could_not_delete_file(&self.temp_path))?;
+        }
+
+        if !self.path.is_file() {
+            return Ok(false);
+        }
+
+        let record = read_yaml_file::<JournalRecord>(&self.path)
+            .map_err(|e| RepoError::invalid_file(&self.path, &e))?;
+        self.apply(&record, true)?;
+        self.finish()?;
+        Ok(true)
+    }
+
+    fn write(&self, record: &JournalRecord) -> RepoResult<()> {
+        let yaml_str = serde_yaml::to_string(record).map_err(RepoError::other)?;
+        let mut file = File::create(&self.temp_path).map_err(RepoError::other)?;
+        file.write_all(yaml_str.as_bytes())
+            .map_err(RepoError::other)?;
+        file.sync_all().map_err(RepoError::other)?;
+        drop(file);
+        rename(&self.temp_path, &self.path).map_err(RepoError::other)
+    }
+
+    // A journal whose actions cannot be completed is rolled back and then
+    // moved aside so that it is not replayed every time the repository is
+    // opened: when replaying, it is not known how far the interrupted
+    // operation got so every step is rolled back
+    fn apply(&self, record: &JournalRecord, replaying: bool) -> RepoResult<()> {
+        for (i, step) in record.actions.iter().enumerate() {
+            if let Err(e) = step.action.apply() {
+                let end = if replaying {
+                    record.actions.len()
+                } else {
+                    i + 1
+                };
+                Self::roll_back(&record.actions[..end]);
+                rename(&self.path, &self.failed_path).map_err(RepoError::other)?;
+                return Err(RepoError::journal_failed(&self.failed_path, &e));
+            }
+        }
+        Ok(())
+    }
+
+    fn roll_back(steps: &[JournalStep]) {
+        for undo in steps.iter().rev().filter_map(|s| s.undo.as_ref()) {
+            if let Err(e) = undo.apply() {
+                warn!("Failed to roll back journal action {undo:?}: {e}");
+            }
+        }
+    }
+
+    fn finish(&self) -> RepoResult<()> {
+        remove_file(&self.path).map_err(|_e| RepoError::could_not_delete_file(&self.path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Journal, JournalAction, JournalRecord, JournalStep};
+    use crate::config::RepoConfig;
+    use anyhow::Result;
+    use chrono::Utc;
+    use std::fs::{create_dir_all, read_to_string, write};
+    use tempdir::TempDir;
+
+    fn steps(actions: Vec<JournalAction>) -> Result<Vec<JournalStep>> {
+        actions
+            .into_iter()
+            .map(|action| {
+                let undo = action.undo()?;
+                Ok(JournalStep { action, undo })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn replay_basics() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let config_path = temp_dir.path().join("config.yaml");
+        let journal = Journal::new(&config_path);
+        assert!(!journal.replay()?);
+
+        let written_path = temp_dir.path().join("a").join("written.txt");
+        let removed_path = temp_dir.path().join("removed.txt");
+        write(&removed_path, "old")?;
+        journal.write(&JournalRecord {
+            operation: String::from("test"),
+            started_at: Utc::now(),
+            actions: steps(vec![
+                JournalAction::WriteFile {
+                    path: written_path.clone(),
+                    contents: String::from("new"),
+                },
+                JournalAction::RemoveFile {
+                    path: removed_path.clone(),
+                },
+            ])?,
+        })?;
+        assert!(journal.path.is_file());
+
+        assert!(journal.replay()?);
+        assert_eq!("new", read_to_string(&written_path)?);
+        assert!(!removed_path.exists());
+        assert!(!journal.path.exists());
+
+        assert!(!journal.replay()?);
+        Ok(())
+    }
+
+    #[test]
+    fn replay_moves() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let journal = Journal::new(&temp_dir.path().join("config.yaml"));
+        let staging_dir = temp_dir.path().join("staging");
+        let shared_dir = temp_dir.path().join("shared");
+        let data_dir = temp_dir.path().join("data");
+        create_dir_all(staging_dir.join("data"))?;
+        create_dir_all(staging_dir.join("shared"))?;
+        create_dir_all(&shared_dir)?;
+        write(staging_dir.join("data").join("file.txt"), "data")?;
+        write(staging_dir.join("shared").join("new.txt"), "new")?;
+        write(staging_dir.join("shared").join("old.txt"), "new")?;
+        write(shared_dir.join("old.txt"), "old")?;
+
+        journal.write(&JournalRecord {
+            operation: String::from("test"),
+            started_at: Utc::now(),
+            actions: steps(vec![
+                JournalAction::MergeDir {
+                    from: staging_dir.join("shared"),
+                    to: shared_dir.clone(),
+                },
+                JournalAction::MoveDir {
+                    from: staging_dir.join("data"),
+                    to: data_dir.clone(),
+                },
+            ])?,
+        })?;
+
+        assert!(journal.replay()?);
+        assert_eq!("data", read_to_string(data_dir.join("file.txt"))?);
+        assert_eq!("new", read_to_string(shared_dir.join("new.txt"))?);
+        assert_eq!("old", read_to_string(shared_dir.join("old.txt"))?);
+        assert!(!staging_dir.join("data").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn uncommitted_journal() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let journal = Journal::new(&temp_dir.path().join("config.yaml"));
+        let written_path = temp_dir.path().join("written.txt");
+        write(
+            &journal.temp_path,
+            format!(
+                "operation: test\nstarted_at: {}\nactions:\n- action: write-file\n  path: {}\n  contents: new\n",
+                Utc::now().to_rfc3339(),
+                written_path.display()
+            ),
+        )?;
+
+        assert!(!journal.replay()?);
+        assert!(!journal.temp_path.exists());
+        assert!(!written_path.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn failed_journal() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let repo_dir = temp_dir.path().join("repo");
+        let blocker_path = temp_dir.path().join("blocker");
+        write(&blocker_path, "")?;
+        let project_dir = temp_dir.path().join("project");
+        drop(RepoConfig::default(&repo_dir, None).repo_blocking()?);
+
+        let journal = Journal::new(&repo_dir.join("config.yaml"));
+        journal.write(&JournalRecord {
+            operation: String::from("test"),
+            started_at: Utc::now(),
+            actions: steps(vec![
+                JournalAction::WriteMarker {
+                    path: project_dir.join(".joat-repo.yaml"),
+                    contents: String::from("marker"),
+                },
+                JournalAction::WriteFile {
+                    path: blocker_path.join("written.txt"),
+                    contents: String::from("new"),
+                },
+            ])?,
+        })?;
+
+        let e = RepoConfig::default(&repo_dir, None)
+            .repo_blocking()
+            .expect_err("replay must fail");
+        assert!(e.is_journal_failed());
+        assert!(!journal.path.exists());
+        assert!(journal.failed_path.is_file());
+        assert!(!project_dir.exists());
+
+        RepoConfig::default(&repo_dir, None).repo_blocking()?;
+        Ok(())
+    }
+
+    #[test]
+    fn replay_on_open() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let repo_dir = temp_dir.path().join("repo");
+        let project_dir = temp_dir.path().join("project");
+        create_dir_all(&project_dir)?;
+
+        let config = RepoConfig::default(&repo_dir, None);
+        let repo = config.repo_blocking()?;
+        let dir_info = repo.init(&project_dir)?.expect("must succeed");
+        let manifest_path = dir_info.manifest_path().to_path_buf();
+        let link_path = dir_info.link_path().to_path_buf();
+        let journal = Journal::new(repo.config_path());
+        drop(repo);
+
+        // Simulate a crash part way through removing the metadirectory
+        journal.write(&JournalRecord {
+            operation: String::from("remove"),
+            started_at: Utc::now(),
+            actions: steps(vec![
+                JournalAction::RemoveFile {
+                    path: link_path.clone(),
+                },
+                JournalAction::RemoveDataDir {
+                    data_dir: dir_info.data_dir().to_path_buf(),
+                },
+            ])?,
+        })?;
+
+        let repo = RepoConfig::default(&repo_dir, None).repo_blocking()?;
+        assert!(!journal.path.exists());
+        assert!(!link_path.exists());
+        assert!(!manifest_path.exists());
+        assert!(repo.get(&project_dir)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn replay_rolls_back() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let journal = Journal::new(&temp_dir.path().join("config.yaml"));
+        let staging_dir = temp_dir.path().join("staging");
+        let shared_dir = temp_dir.path().join("shared");
+        let data_dir = temp_dir.path().join("data");
+        let blocker_path = temp_dir.path().join("blocker");
+        create_dir_all(staging_dir.join("data"))?;
+        create_dir_all(staging_dir.join("shared"))?;
+        create_dir_all(&shared_dir)?;
+        write(staging_dir.join("shared").join("new.txt"), "new")?;
+        write(shared_dir.join("old.txt"), "old")?;
+        write(&blocker_path, "")?;
+
+        journal.write(&JournalRecord {
+            operation: String::from("test"),
+            started_at: Utc::now(),
+            actions: steps(vec![
+                JournalAction::MergeDir {
+                    from: staging_dir.join("shared"),
+                    to: shared_dir.clone(),
+                },
+                JournalAction::MoveDir {
+                    from: staging_dir.join("data"),
+                    to: data_dir.clone(),
+                },
+                JournalAction::WriteFile {
+                    path: blocker_path.join("written.txt"),
+                    contents: String::from("new"),
+                },
+            ])?,
+        })?;
+
+        let e = journal.replay().expect_err("replay must fail");
+        assert!(e.is_journal_failed());
+        assert!(journal.failed_path.is_file());
+        assert!(staging_dir.join("data").is_dir());
+        assert!(!data_dir.exists());
+        assert_eq!(
+            "new",
+            read_to_string(staging_dir.join("shared").join("new.txt"))?
+        );
+        assert!(!shared_dir.join("new.txt").exists());
+        assert_eq!("old", read_to_string(shared_dir.join("old.txt"))?);
+        Ok(())
+    }
+}
@@ -30,27 +30,64 @@
 #![allow(clippy::match_wildcard_for_single_variants)]
 #![allow(clippy::missing_errors_doc)]
 #![allow(clippy::module_name_repetitions)]
+#![allow(clippy::multiple_crate_versions)]
 #![allow(clippy::option_if_let_else)]
+#![allow(clippy::struct_field_names)]
+mod access_stats;
+mod archive;
+mod backup;
+mod check;
 mod config;
+mod config_loader;
+mod data_dir_lock;
 mod dir_info;
+mod disk_usage;
 mod error;
+mod journal;
 mod link;
+mod link_hash_algorithm;
 mod link_id;
+mod lock;
 mod manifest;
 mod meta_id;
+mod meta_ref;
+mod metadata;
+mod path_policy;
+mod path_serde;
+mod project_marker;
+mod quarantine;
+mod reachability;
+mod read_only_repo;
+mod removal;
 mod repo;
 mod result;
+mod schema;
 mod shared_path;
 mod trash;
+mod trash_policy;
 
+pub use self::archive::{ArchiveCompression, ExportOptions, ImportOptions};
+pub use self::check::{CheckProblem, CheckProblemKind, Repair};
 pub use self::config::RepoConfig;
+pub use self::config_loader::{ConfigEntry, ConfigLoader, ConfigSource, LayeredConfig, ENV_PREFIX};
+pub use self::data_dir_lock::DataDirLock;
 pub use self::dir_info::DirInfo;
+pub use self::disk_usage::{DiskUsage, ManifestUsage, UsageReport};
 pub use self::error::{RepoError, RepoErrorKind};
 pub use self::link::Link;
+pub use self::link_hash_algorithm::LinkHashAlgorithm;
 pub use self::link_id::LinkId;
+pub use self::lock::{LockMode, LockOwner};
 pub use self::manifest::Manifest;
 pub use self::meta_id::MetaId;
+pub use self::metadata::Metadata;
+pub use self::path_policy::PathPolicy;
+pub use self::project_marker::MovedProject;
+pub use self::quarantine::QuarantineEntry;
+pub use self::read_only_repo::ReadOnlyRepo;
+pub use self::removal::Removal;
 pub use self::repo::Repo;
 pub use self::result::RepoResult;
 pub use self::shared_path::SharedPath;
-pub use self::trash::Trash;
+pub use self::trash::{Trash, TrashEntry, TrashReason};
+pub use self::trash_policy::TrashPolicy;
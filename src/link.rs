@@ -19,21 +19,32 @@
 // OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
 // WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 //
+use crate::link_hash_algorithm::LinkHashAlgorithm;
 use crate::link_id::LinkId;
 use crate::meta_id::MetaId;
+use crate::metadata::{ExtraFields, Metadata};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct LinkRecord {
+    #[serde(default)]
+    pub(crate) schema_version: u32,
     pub(crate) created_at: DateTime<Utc>,
     pub(crate) link_id: LinkId,
+    #[serde(default = "LinkHashAlgorithm::legacy")]
+    pub(crate) hash_algorithm: LinkHashAlgorithm,
+    #[serde(with = "crate::path_serde")]
     pub(crate) project_dir: PathBuf,
     pub(crate) meta_id: MetaId,
+    #[serde(default, skip_serializing_if = "Metadata::is_empty")]
+    pub(crate) metadata: Metadata,
+    #[serde(flatten)]
+    pub(crate) extra: ExtraFields,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Link {
     link_path: PathBuf,
     record: LinkRecord,
@@ -44,6 +55,10 @@ impl Link {
         Self { link_path, record }
     }
 
+    pub(crate) const fn record(&self) -> &LinkRecord {
+        &self.record
+    }
+
     #[must_use]
     pub fn link_path(&self) -> &Path {
         &self.link_path
@@ -59,6 +74,11 @@ impl Link {
         &self.record.link_id
     }
 
+    #[must_use]
+    pub const fn hash_algorithm(&self) -> LinkHashAlgorithm {
+        self.record.hash_algorithm
+    }
+
     #[must_use]
     pub fn project_dir(&self) -> &Path {
         &self.record.project_dir
@@ -68,4 +88,59 @@ impl Link {
     pub const fn meta_id(&self) -> &MetaId {
         &self.record.meta_id
     }
+
+    #[must_use]
+    pub const fn metadata(&self) -> &Metadata {
+        &self.record.metadata
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LinkRecord;
+    use crate::link_hash_algorithm::LinkHashAlgorithm;
+    use crate::link_id::LinkId;
+    use anyhow::Result;
+    use std::path::Path;
+
+    #[cfg(unix)]
+    #[test]
+    fn legacy_link_record() -> Result<()> {
+        let record = serde_yaml::from_str::<LinkRecord>(
+            "created_at: 2023-12-01T17:36:05.123456789Z\n\
+             link_id: 90722f2638004be06d790eaac9ac1f8a\n\
+             project_dir: /home/user/project\n\
+             meta_id: 41941524f6da41dfa06fdb5c55f32a3d\n",
+        )?;
+        assert_eq!(Path::new("/home/user/project"), record.project_dir);
+        assert_eq!(LinkHashAlgorithm::Md5, record.hash_algorithm);
+        assert_eq!(
+            record.link_id,
+            LinkId::try_from(&record.project_dir as &Path)?
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unknown_fields() -> Result<()> {
+        let record = serde_yaml::from_str::<LinkRecord>(
+            "created_at: 2023-12-01T17:36:05.123456789Z\n\
+             link_id: 90722f2638004be06d790eaac9ac1f8a\n\
+             project_dir: /home/user/project\n\
+             meta_id: 41941524f6da41dfa06fdb5c55f32a3d\n\
+             metadata:\n  \
+               name: Project\n  \
+               colour: blue\n\
+             future_field:\n  \
+               a: 1\n",
+        )?;
+        assert_eq!(Some("Project"), record.metadata.name.as_deref());
+
+        let yaml_str = serde_yaml::to_string(&record)?;
+        assert!(yaml_str.contains("colour: blue"));
+        assert!(yaml_str.contains("future_field:"));
+        assert!(yaml_str.contains("  a: 1"));
+        Ok(())
+    }
 }
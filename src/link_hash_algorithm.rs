@@ -0,0 +1,75 @@
+// Copyright (c) 2023 Richard Cook
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkHashAlgorithm {
+    Md5,
+    #[default]
+    Sha256,
+    Blake3,
+}
+
+impl LinkHashAlgorithm {
+    pub const ALL: [Self; 3] = [Self::Md5, Self::Sha256, Self::Blake3];
+
+    #[must_use]
+    pub const fn legacy() -> Self {
+        Self::Md5
+    }
+
+    pub(crate) const fn prefix(self) -> Option<&'static str> {
+        match self {
+            Self::Md5 => None,
+            Self::Sha256 => Some("sha256"),
+            Self::Blake3 => Some("blake3"),
+        }
+    }
+
+    pub(crate) const fn digest_len(self) -> usize {
+        match self {
+            Self::Md5 => 32,
+            Self::Sha256 | Self::Blake3 => 64,
+        }
+    }
+
+    pub(crate) fn hex_digest(self, bytes: &[u8]) -> String {
+        match self {
+            Self::Md5 => format!("{:x}", md5::compute(bytes)),
+            Self::Sha256 => format!("{:x}", Sha256::digest(bytes)),
+            Self::Blake3 => blake3::hash(bytes).to_hex().to_string(),
+        }
+    }
+}
+
+impl Display for LinkHashAlgorithm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Md5 => write!(f, "md5"),
+            Self::Sha256 => write!(f, "sha256"),
+            Self::Blake3 => write!(f, "blake3"),
+        }
+    }
+}
@@ -20,7 +20,7 @@
 // WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 //
 use crate::error::RepoError;
-use md5::compute;
+use crate::link_hash_algorithm::LinkHashAlgorithm;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt::{Display, Formatter, Result as FmtResult};
 use std::path::Path;
@@ -30,12 +30,49 @@ use std::str::FromStr;
 #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct LinkId(String);
 
+impl LinkId {
+    pub fn compute(path: &Path, algorithm: LinkHashAlgorithm) -> StdResult<Self, RepoError> {
+        if !path.is_absolute() {
+            return Err(RepoError::could_not_compute_hash(path));
+        }
+
+        let digest = algorithm.hex_digest(path_bytes(path)?);
+        Ok(Self(match algorithm.prefix() {
+            Some(prefix) => format!("{prefix}-{digest}"),
+            None => digest,
+        }))
+    }
+
+    #[must_use]
+    pub fn algorithm(&self) -> LinkHashAlgorithm {
+        LinkHashAlgorithm::ALL
+            .into_iter()
+            .find(|a| {
+                a.prefix()
+                    .is_some_and(|prefix| self.0.starts_with(&format!("{prefix}-")))
+            })
+            .unwrap_or_else(LinkHashAlgorithm::legacy)
+    }
+}
+
 impl FromStr for LinkId {
     type Err = RepoError;
 
     fn from_str(s: &str) -> StdResult<Self, Self::Err> {
-        if !s.is_empty() && s.chars().all(|c| c.is_ascii_hexdigit()) {
-            Ok(Self(s.to_lowercase()))
+        let lower = s.to_lowercase();
+        let (algorithm, digest) = match lower.split_once('-') {
+            Some((prefix, digest)) => (
+                LinkHashAlgorithm::ALL
+                    .into_iter()
+                    .find(|a| a.prefix() == Some(prefix))
+                    .ok_or_else(|| RepoError::invalid_link_id(s))?,
+                digest,
+            ),
+            None => (LinkHashAlgorithm::legacy(), lower.as_str()),
+        };
+
+        if digest.len() == algorithm.digest_len() && digest.chars().all(|c| c.is_ascii_hexdigit()) {
+            Ok(Self(lower))
         } else {
             Err(RepoError::invalid_link_id(s))
         }
@@ -46,18 +83,24 @@ impl TryFrom<&Path> for LinkId {
     type Error = RepoError;
 
     fn try_from(value: &Path) -> StdResult<Self, Self::Error> {
-        if !value.is_absolute() {
-            return Err(RepoError::could_not_compute_hash(value));
-        }
-
-        let s = value
-            .to_str()
-            .ok_or_else(|| RepoError::could_not_compute_hash(value))?;
-        let digest = compute(s);
-        format!("{digest:x}").parse::<Self>()
+        Self::compute(value, LinkHashAlgorithm::legacy())
     }
 }
 
+#[cfg(unix)]
+#[allow(clippy::unnecessary_wraps)]
+fn path_bytes(path: &Path) -> StdResult<&[u8], RepoError> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(path.as_os_str().as_bytes())
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> StdResult<&[u8], RepoError> {
+    path.to_str()
+        .map(str::as_bytes)
+        .ok_or_else(|| RepoError::could_not_compute_hash(path))
+}
+
 impl Display for LinkId {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
         write!(f, "{}", self.0)
@@ -85,6 +128,7 @@ impl<'de> Deserialize<'de> for LinkId {
 #[cfg(test)]
 mod tests {
     use super::LinkId;
+    use crate::link_hash_algorithm::LinkHashAlgorithm;
     use anyhow::Result;
     use rstest::rstest;
     use std::path::{Path, PathBuf};
@@ -105,15 +149,58 @@ mod tests {
         assert!(LinkId::try_from(&absolute_path() as &Path).is_ok());
     }
 
+    #[cfg(unix)]
+    #[rstest]
+    #[case("dcd8c9a7c3e6900eb0aca946f011dafa", b"/absolute")]
+    #[case("90722f2638004be06d790eaac9ac1f8a", b"/home/user/project")]
+    #[case("1a2b0784e569bdfaaf419b5d452ff9fc", b"/caf\xe9")]
+    #[case("e3b4e2bde9a045fc78f5d157ea58fc31", b"/tmp/\xff\xfe")]
+    fn from_path_stable(#[case] expected_str: &str, #[case] input: &[u8]) -> Result<()> {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        let path = Path::new(OsStr::from_bytes(input));
+        assert_eq!(expected_str, LinkId::try_from(path)?.to_string());
+        Ok(())
+    }
+
     #[test]
     fn from_path_not_absolute_path() {
         assert!(LinkId::try_from(Path::new("garbage")).is_err());
     }
 
+    #[cfg(unix)]
     #[rstest]
-    #[case("abcdef")]
-    #[case("ABCDEF")]
-    #[case("123456")]
+    #[case("dcd8c9a7c3e6900eb0aca946f011dafa", LinkHashAlgorithm::Md5)]
+    #[case(
+        "sha256-b21164e245cae7ceb61ffb10a72975bd4da455e156ed03708df96d06ef70e169",
+        LinkHashAlgorithm::Sha256
+    )]
+    fn compute_basics(
+        #[case] expected_str: &str,
+        #[case] algorithm: LinkHashAlgorithm,
+    ) -> Result<()> {
+        let link_id = LinkId::compute(Path::new("/absolute"), algorithm)?;
+        assert_eq!(expected_str, link_id.to_string());
+        assert_eq!(algorithm, link_id.algorithm());
+        assert_eq!(link_id, expected_str.parse::<LinkId>()?);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn compute_blake3() -> Result<()> {
+        let link_id = LinkId::compute(Path::new("/absolute"), LinkHashAlgorithm::Blake3)?;
+        assert!(link_id.to_string().starts_with("blake3-"));
+        assert_eq!(LinkHashAlgorithm::Blake3, link_id.algorithm());
+        assert_eq!(link_id, link_id.to_string().parse::<LinkId>()?);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("abcdefabcdefabcdefabcdefabcdefab")]
+    #[case("ABCDEFABCDEFABCDEFABCDEFABCDEFAB")]
+    #[case("12345612345612345612345612345612")]
+    #[case("sha256-b21164e245cae7ceb61ffb10a72975bd4da455e156ed03708df96d06ef70e169")]
     fn parse_basics(#[case] input: &str) -> Result<()> {
         assert_eq!(input.to_lowercase(), input.parse::<LinkId>()?.to_string());
         Ok(())
@@ -124,6 +211,9 @@ mod tests {
     #[case("")]
     #[case("  ")]
     #[case("  abcdef  ")]
+    #[case("abcdef")]
+    #[case("sha256-abcdefabcdefabcdefabcdefabcdefab")]
+    #[case("sha1-abcdefabcdefabcdefabcdefabcdefab")]
     fn parse_errors(#[case] input: &str) {
         assert!(input.parse::<LinkId>().is_err());
     }
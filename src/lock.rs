@@ -0,0 +1,340 @@
+// Copyright (c) 2023 Richard Cook
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+use crate::error::RepoError;
+use crate::result::RepoResult;
+use fs4::{lock_contended_error, FileExt};
+use std::cmp::min;
+#[cfg(target_os = "linux")]
+use std::fs::read_link;
+use std::fs::{create_dir_all, read_to_string, remove_file, File, OpenOptions};
+use std::io::{Error as IOError, ErrorKind, Result as IOResult, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::id;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+const INITIAL_LOCK_RETRY_DELAY: Duration = Duration::from_millis(10);
+const MAX_LOCK_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LockMode {
+    Shared,
+    Exclusive,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LockOwner {
+    mode: LockMode,
+    pid: Option<u32>,
+    host: Option<HostIdentity>,
+}
+
+impl LockOwner {
+    #[must_use]
+    pub const fn mode(&self) -> LockMode {
+        self.mode
+    }
+
+    #[must_use]
+    pub const fn pid(&self) -> Option<u32> {
+        self.pid
+    }
+
+    #[must_use]
+    pub fn host_name(&self) -> Option<&str> {
+        self.host.as_ref().map(|h| h.name.as_str())
+    }
+
+    // A PID only identifies a process on the same host and in the same PID
+    // namespace: anywhere else its absence from /proc proves nothing
+    #[must_use]
+    pub fn is_alive(&self) -> Option<bool> {
+        let pid = self.pid?;
+        if self.host.is_none() || self.host != HostIdentity::current() {
+            return None;
+        }
+        is_process_alive(pid)
+    }
+
+    #[must_use]
+    pub fn is_stale(&self) -> bool {
+        self.mode == LockMode::Exclusive && self.is_alive() == Some(false)
+    }
+
+    fn parse(mode: LockMode, s: &str) -> Self {
+        let mut lines = s.lines().map(str::trim);
+        let pid = lines.next().and_then(|s| s.parse::<u32>().ok());
+        let host = match (lines.next(), lines.next()) {
+            (Some(name), Some(pid_namespace)) if !name.is_empty() => Some(HostIdentity {
+                name: String::from(name),
+                pid_namespace: String::from(pid_namespace),
+            }),
+            _ => None,
+        };
+        Self { mode, pid, host }
+    }
+
+    fn current() -> String {
+        HostIdentity::current().map_or_else(
+            || format!("{}", id()),
+            |h| format!("{}\n{}\n{}\n", id(), h.name, h.pid_namespace),
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct HostIdentity {
+    name: String,
+    pid_namespace: String,
+}
+
+impl HostIdentity {
+    #[cfg(target_os = "linux")]
+    fn current() -> Option<Self> {
+        let name = read_to_string("/proc/sys/kernel/hostname").ok()?;
+        let pid_namespace = read_link("/proc/self/ns/pid").ok()?;
+        Some(Self {
+            name: String::from(name.trim()),
+            pid_namespace: pid_namespace.to_str()?.to_string(),
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    const fn current() -> Option<Self> {
+        None
+    }
+}
+
+#[derive(Debug)]
+pub struct RepoLock {
+    path: PathBuf,
+    file: File,
+    mode: Option<LockMode>,
+}
+
+impl RepoLock {
+    pub fn open(path: &Path) -> RepoResult<Self> {
+        if let Some(dir) = path.parent() {
+            create_dir_all(dir).map_err(RepoError::other)?;
+        }
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(|_e| RepoError::could_not_open_lock_file(path))?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            mode: None,
+        })
+    }
+
+    pub fn owner(path: &Path) -> RepoResult<Option<LockOwner>> {
+        let file = match OpenOptions::new().read(true).write(true).open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(_) => return Err(RepoError::could_not_open_lock_file(path)),
+        };
+
+        match FileExt::try_lock_exclusive(&file) {
+            Ok(()) => {
+                _ = FileExt::unlock(&file);
+                return Ok(None);
+            }
+            Err(e) if Self::is_contended(&e) => {}
+            Err(_) => return Err(RepoError::could_not_lock(path)),
+        }
+
+        // Shared holders do not record themselves so any PID in the file
+        // is left over from an earlier exclusive holder
+        match FileExt::try_lock_shared(&file) {
+            Ok(()) => {
+                _ = FileExt::unlock(&file);
+                Ok(Some(LockOwner {
+                    mode: LockMode::Shared,
+                    pid: None,
+                    host: None,
+                }))
+            }
+            Err(e) if Self::is_contended(&e) => Ok(Some(LockOwner::parse(
+                LockMode::Exclusive,
+                &read_to_string(path).unwrap_or_default(),
+            ))),
+            Err(_) => Err(RepoError::could_not_lock(path)),
+        }
+    }
+
+    pub fn break_lock(path: &Path) -> RepoResult<bool> {
+        let Some(owner) = Self::owner(path)? else {
+            return Ok(false);
+        };
+
+        // Only break a lock whose exclusive holder is known to have exited:
+        // unlinking a lock file that is still held would let a second
+        // process lock a new file at the same path
+        if !owner.is_stale() {
+            return Ok(false);
+        }
+
+        remove_file(path).map_err(|_e| RepoError::could_not_delete_file(path))?;
+        Ok(true)
+    }
+
+    pub fn try_lock(&mut self, mode: LockMode) -> RepoResult<bool> {
+        let result = match mode {
+            LockMode::Shared => FileExt::try_lock_shared(&self.file),
+            LockMode::Exclusive => FileExt::try_lock_exclusive(&self.file),
+        };
+        match result {
+            Ok(()) => {
+                self.locked(mode)?;
+                Ok(true)
+            }
+            Err(e) if Self::is_contended(&e) => Ok(false),
+            Err(_) => Err(RepoError::could_not_lock(&self.path)),
+        }
+    }
+
+    pub fn lock(&mut self, mode: LockMode) -> RepoResult<()> {
+        match mode {
+            LockMode::Shared => FileExt::lock_shared(&self.file),
+            LockMode::Exclusive => FileExt::lock_exclusive(&self.file),
+        }
+        .map_err(|_e| RepoError::could_not_lock(&self.path))?;
+        self.locked(mode)
+    }
+
+    pub fn lock_with_timeout(&mut self, mode: LockMode, timeout: Duration) -> RepoResult<()> {
+        let deadline = Instant::now() + timeout;
+        let mut delay = INITIAL_LOCK_RETRY_DELAY;
+        loop {
+            if self.try_lock(mode)? {
+                return Ok(());
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(RepoError::lock_timed_out(&self.path, timeout));
+            }
+
+            sleep(min(delay, deadline - now));
+            delay = min(delay * 2, MAX_LOCK_RETRY_DELAY);
+        }
+    }
+
+    fn locked(&mut self, mode: LockMode) -> RepoResult<()> {
+        self.mode = Some(mode);
+        if mode == LockMode::Exclusive {
+            self.write_pid(&LockOwner::current())
+                .map_err(|_e| RepoError::could_not_lock(&self.path))?;
+        }
+        Ok(())
+    }
+
+    fn write_pid(&mut self, s: &str) -> IOResult<()> {
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(s.as_bytes())?;
+        self.file.flush()
+    }
+
+    fn is_contended(e: &IOError) -> bool {
+        e.kind() == ErrorKind::WouldBlock
+            || e.raw_os_error() == lock_contended_error().raw_os_error()
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[allow(clippy::unnecessary_wraps)]
+fn is_process_alive(pid: u32) -> Option<bool> {
+    Some(Path::new("/proc").join(format!("{pid}")).is_dir())
+}
+
+#[cfg(not(target_os = "linux"))]
+const fn is_process_alive(_pid: u32) -> Option<bool> {
+    None
+}
+
+impl Drop for RepoLock {
+    fn drop(&mut self) {
+        if let Some(mode) = self.mode.take() {
+            if mode == LockMode::Exclusive {
+                _ = self.write_pid("");
+            }
+            _ = FileExt::unlock(&self.file);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LockMode, LockOwner};
+    use std::process::id;
+
+    #[test]
+    fn owner_no_pid() {
+        let owner = LockOwner::parse(LockMode::Exclusive, "");
+        assert_eq!(None, owner.is_alive());
+        assert!(!owner.is_stale());
+    }
+
+    #[test]
+    fn owner_unknown_host() {
+        let owner = LockOwner::parse(LockMode::Exclusive, &format!("{}", u32::MAX));
+        assert_eq!(Some(u32::MAX), owner.pid());
+        assert_eq!(None, owner.is_alive());
+        assert!(!owner.is_stale());
+
+        let owner = LockOwner::parse(
+            LockMode::Exclusive,
+            &format!("{}\nother-host\npid:[1]\n", u32::MAX),
+        );
+        assert_eq!(Some("other-host"), owner.host_name());
+        assert_eq!(None, owner.is_alive());
+        assert!(!owner.is_stale());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn owner_alive() {
+        let owner = LockOwner::parse(LockMode::Exclusive, &LockOwner::current());
+        assert_eq!(Some(id()), owner.pid());
+        assert_eq!(Some(true), owner.is_alive());
+        assert!(!owner.is_stale());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn owner_dead() {
+        let s = LockOwner::current().replacen(&format!("{}", id()), &format!("{}", u32::MAX), 1);
+        let owner = LockOwner::parse(LockMode::Exclusive, &s);
+        assert_eq!(Some(false), owner.is_alive());
+        assert!(owner.is_stale());
+
+        let owner = LockOwner::parse(LockMode::Shared, &s);
+        assert!(!owner.is_stale());
+    }
+}
@@ -19,16 +19,30 @@
 // OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
 // WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 //
+use crate::access_stats::AccessStats;
+use crate::disk_usage::{DiskUsage, DiskUsageWalker};
+use crate::error::RepoError;
 use crate::meta_id::MetaId;
+use crate::metadata::{ExtraFields, Metadata};
+use crate::result::RepoResult;
 use chrono::{DateTime, Utc};
+use joatmon::safe_write_file;
+use log::warn;
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct ManifestRecord {
+    #[serde(default)]
+    pub(crate) schema_version: u32,
     pub(crate) created_at: DateTime<Utc>,
+    #[serde(with = "crate::path_serde")]
     pub(crate) original_project_dir: PathBuf,
     pub(crate) meta_id: MetaId,
+    #[serde(default, skip_serializing_if = "Metadata::is_empty")]
+    pub(crate) metadata: Metadata,
+    #[serde(flatten)]
+    pub(crate) extra: ExtraFields,
 }
 
 #[derive(Clone, Debug)]
@@ -36,18 +50,17 @@ pub struct Manifest {
     data_dir: PathBuf,
     manifest_path: PathBuf,
     record: ManifestRecord,
+    access_stats: AccessStats,
 }
 
 impl Manifest {
-    pub(crate) const fn new(
-        data_dir: PathBuf,
-        manifest_path: PathBuf,
-        record: ManifestRecord,
-    ) -> Self {
+    pub(crate) fn new(data_dir: PathBuf, manifest_path: PathBuf, record: ManifestRecord) -> Self {
+        let access_stats = AccessStats::read(&data_dir);
         Self {
             data_dir,
             manifest_path,
             record,
+            access_stats,
         }
     }
 
@@ -75,4 +88,46 @@ impl Manifest {
     pub const fn meta_id(&self) -> &MetaId {
         &self.record.meta_id
     }
+
+    #[must_use]
+    pub const fn last_accessed_at(&self) -> Option<&DateTime<Utc>> {
+        self.access_stats.last_accessed_at()
+    }
+
+    #[must_use]
+    pub const fn access_count(&self) -> u64 {
+        self.access_stats.access_count()
+    }
+
+    #[must_use]
+    pub const fn metadata(&self) -> &Metadata {
+        &self.record.metadata
+    }
+
+    pub fn disk_usage(&self) -> RepoResult<DiskUsage> {
+        DiskUsageWalker::default().walk(&self.data_dir)
+    }
+
+    // Access statistics are informational only so failing to update them
+    // must never fail the operation that resolved the metadirectory
+    pub(crate) fn record_access(&mut self, now: DateTime<Utc>) {
+        match AccessStats::record(&self.data_dir, now) {
+            Ok(access_stats) => self.access_stats = access_stats,
+            Err(e) => warn!(
+                "Could not record access to metadirectory {}: {e}",
+                self.record.meta_id
+            ),
+        }
+    }
+
+    pub(crate) fn set_metadata(&mut self, metadata: Metadata) -> RepoResult<()> {
+        self.record.metadata = metadata;
+        self.write()
+    }
+
+    fn write(&self) -> RepoResult<()> {
+        let yaml_str = serde_yaml::to_string(&self.record).map_err(RepoError::other)?;
+        safe_write_file(&self.manifest_path, yaml_str, true).map_err(RepoError::other)?;
+        Ok(())
+    }
 }
@@ -0,0 +1,151 @@
+// Copyright (c) 2023 Richard Cook
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+use crate::error::RepoError;
+use crate::meta_id::MetaId;
+use crate::result::RepoResult;
+
+const MIN_PREFIX_LEN: usize = 4;
+
+pub fn resolve_meta_ref<'a, I>(s: &str, candidates: I) -> RepoResult<MetaId>
+where
+    I: IntoIterator<Item = (&'a MetaId, Option<&'a str>)>,
+{
+    let candidates = candidates.into_iter().collect::<Vec<_>>();
+
+    if let Ok(meta_id) = s.parse::<MetaId>() {
+        return if candidates.iter().any(|(id, _)| **id == meta_id) {
+            Ok(meta_id)
+        } else {
+            Err(RepoError::meta_ref_not_found(s))
+        };
+    }
+
+    let named = candidates
+        .iter()
+        .filter(|(_, name)| *name == Some(s))
+        .map(|(id, _)| (*id).clone())
+        .collect::<Vec<_>>();
+    match named.len() {
+        0 => {}
+        1 => return Ok(named[0].clone()),
+        _ => return Err(RepoError::ambiguous_meta_ref(s, &named)),
+    }
+
+    if !is_meta_id_prefix(s) {
+        return Err(RepoError::meta_ref_not_found(s));
+    }
+    let prefix = s.to_lowercase();
+
+    let matches = candidates
+        .iter()
+        .filter(|(id, _)| id.to_string().starts_with(&prefix))
+        .map(|(id, _)| (*id).clone())
+        .collect::<Vec<_>>();
+    match matches.len() {
+        0 => Err(RepoError::meta_ref_not_found(s)),
+        1 => Ok(matches[0].clone()),
+        _ => Err(RepoError::ambiguous_meta_ref(s, &matches)),
+    }
+}
+
+// Names that look like meta ID prefixes would shadow prefix lookups in
+// resolve_meta_ref
+pub fn validate_meta_name(s: &str) -> RepoResult<()> {
+    if s.trim().is_empty() || s.trim() != s || s.parse::<MetaId>().is_ok() || is_meta_id_prefix(s) {
+        return Err(RepoError::invalid_meta_name(s));
+    }
+    Ok(())
+}
+
+fn is_meta_id_prefix(s: &str) -> bool {
+    s.len() >= MIN_PREFIX_LEN && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{resolve_meta_ref, validate_meta_name};
+    use crate::meta_id::MetaId;
+    use anyhow::Result;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(
+        Some("41941524f6da41dfa06fdb5c55f32a3d"),
+        "41941524f6da41dfa06fdb5c55f32a3d"
+    )]
+    #[case(Some("41941524f6da41dfa06fdb5c55f32a3d"), "41941")]
+    #[case(Some("41941524f6da41dfa06fdb5c55f32a3d"), "41941524F6DA")]
+    #[case(Some("41941524f6da41dfa06fdb5c55f32a3d"), "first")]
+    #[case(Some("4194aaaaaaaaaaaaaaaaaaaaaaaaaaaa"), "second")]
+    #[case(Some("bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb"), "bbbb")]
+    #[case(None, "419")]
+    #[case(None, "4194")]
+    #[case(None, "cccc")]
+    #[case(None, "cccccccccccccccccccccccccccccccc")]
+    #[case(None, "third")]
+    fn resolve_basics(#[case] expected_result: Option<&str>, #[case] input: &str) -> Result<()> {
+        let first = "41941524f6da41dfa06fdb5c55f32a3d".parse::<MetaId>()?;
+        let second = "4194aaaaaaaaaaaaaaaaaaaaaaaaaaaa".parse::<MetaId>()?;
+        let third = "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb".parse::<MetaId>()?;
+        let candidates = vec![
+            (&first, Some("first")),
+            (&second, Some("second")),
+            (&third, None),
+        ];
+
+        let result = resolve_meta_ref(input, candidates);
+        match expected_result {
+            Some(s) => assert_eq!(s.parse::<MetaId>()?, result?),
+            None => assert!(result.is_err()),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_errors() -> Result<()> {
+        let first = "41941524f6da41dfa06fdb5c55f32a3d".parse::<MetaId>()?;
+        let second = "4194aaaaaaaaaaaaaaaaaaaaaaaaaaaa".parse::<MetaId>()?;
+        let candidates = vec![(&first, None), (&second, None)];
+
+        assert!(resolve_meta_ref("4194", candidates.clone())
+            .expect_err("must fail")
+            .is_ambiguous_meta_ref());
+        assert!(resolve_meta_ref("cccc", candidates)
+            .expect_err("must fail")
+            .is_meta_ref_not_found());
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(true, "project")]
+    #[case(true, "my project")]
+    #[case(false, "")]
+    #[case(false, " project")]
+    #[case(true, "abc")]
+    #[case(true, "cafe-au-lait")]
+    #[case(false, "cafe")]
+    #[case(false, "BEEF1")]
+    #[case(false, "41941524f6da41dfa06fdb5c55f32a3d")]
+    fn validate_basics(#[case] expected_result: bool, #[case] input: &str) {
+        assert_eq!(expected_result, validate_meta_name(input).is_ok());
+    }
+}
@@ -0,0 +1,129 @@
+// Copyright (c) 2023 Richard Cook
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+use crate::error::RepoError;
+use crate::result::RepoResult;
+use path_absolutize::Absolutize;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs::canonicalize;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PathPolicy {
+    Verbatim,
+    #[default]
+    Lexical,
+    Resolve,
+}
+
+impl PathPolicy {
+    #[must_use]
+    pub const fn legacy() -> Self {
+        Self::Verbatim
+    }
+
+    pub fn apply(self, path: &Path) -> RepoResult<PathBuf> {
+        if !path.is_absolute() {
+            return Ok(path.to_path_buf());
+        }
+
+        match self {
+            Self::Verbatim => Ok(path.to_path_buf()),
+            Self::Lexical => Self::normalize(path),
+            Self::Resolve => {
+                canonicalize(path).map_err(|e| RepoError::could_not_resolve_path(path, &e))
+            }
+        }
+    }
+
+    fn normalize(path: &Path) -> RepoResult<PathBuf> {
+        Ok(path
+            .absolutize()
+            .map_err(RepoError::other)?
+            .components()
+            .collect())
+    }
+}
+
+impl Display for PathPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Verbatim => write!(f, "verbatim"),
+            Self::Lexical => write!(f, "lexical"),
+            Self::Resolve => write!(f, "resolve"),
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::PathPolicy;
+    use anyhow::Result;
+    use rstest::rstest;
+    use std::fs::create_dir_all;
+    use std::os::unix::fs::symlink;
+    use std::path::Path;
+    use tempdir::TempDir;
+
+    #[rstest]
+    #[case(PathPolicy::Verbatim, "/home/me/proj/", "/home/me/proj/")]
+    #[case(PathPolicy::Verbatim, "/home/me/./proj", "/home/me/./proj")]
+    #[case(PathPolicy::Lexical, "/home/me/proj", "/home/me/proj")]
+    #[case(PathPolicy::Lexical, "/home/me/proj", "/home/me/proj/")]
+    #[case(PathPolicy::Lexical, "/home/me/proj", "/home/me//proj")]
+    #[case(PathPolicy::Lexical, "/home/me/proj", "/home/me/./proj")]
+    #[case(PathPolicy::Lexical, "/home/me/proj", "/home/me/other/../proj")]
+    #[case(PathPolicy::Lexical, "relative/", "relative/")]
+    fn apply_basics(
+        #[case] policy: PathPolicy,
+        #[case] expected_str: &str,
+        #[case] input: &str,
+    ) -> Result<()> {
+        assert_eq!(
+            expected_str,
+            policy
+                .apply(Path::new(input))?
+                .to_str()
+                .expect("must be valid")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn apply_resolve() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let base_dir = temp_dir.path().canonicalize()?;
+        let project_dir = base_dir.join("project");
+        create_dir_all(&project_dir)?;
+        let link_dir = base_dir.join("link");
+        symlink(&project_dir, &link_dir)?;
+
+        assert_eq!(project_dir, PathPolicy::Resolve.apply(&link_dir)?);
+        assert_eq!(link_dir, PathPolicy::Lexical.apply(&link_dir)?);
+        assert!(PathPolicy::Resolve
+            .apply(&base_dir.join("missing/"))
+            .expect_err("must fail")
+            .is_could_not_resolve_path());
+        Ok(())
+    }
+}
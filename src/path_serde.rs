@@ -0,0 +1,150 @@
+// Copyright (c) 2023 Richard Cook
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+use serde::de::Error as DeError;
+use serde::ser::Error as SerError;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::path::{Path, PathBuf};
+use std::result::Result as StdResult;
+
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum PathRepr {
+    Text(String),
+    Raw { raw: Vec<u8> },
+}
+
+pub fn serialize<S>(path: &Path, serializer: S) -> StdResult<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match path.to_str() {
+        Some(s) => serializer.serialize_str(s),
+        None => PathRepr::Raw {
+            raw: to_raw(path).ok_or_else(|| {
+                S::Error::custom(format!("path {} is not valid Unicode", path.display()))
+            })?,
+        }
+        .serialize(serializer),
+    }
+}
+
+pub fn deserialize<'de, D>(deserializer: D) -> StdResult<PathBuf, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match PathRepr::deserialize(deserializer)? {
+        PathRepr::Text(s) => Ok(PathBuf::from(s)),
+        PathRepr::Raw { raw } => {
+            from_raw(raw).ok_or_else(|| D::Error::custom("raw paths are not supported"))
+        }
+    }
+}
+
+pub mod vec {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::path::PathBuf;
+    use std::result::Result as StdResult;
+
+    #[derive(Deserialize, Serialize)]
+    struct PathItem(#[serde(with = "super")] PathBuf);
+
+    #[allow(clippy::ptr_arg)]
+    pub fn serialize<S>(paths: &Vec<PathBuf>, serializer: S) -> StdResult<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(paths.iter().map(|p| PathItem(p.clone())))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> StdResult<Vec<PathBuf>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(Vec::<PathItem>::deserialize(deserializer)?
+            .into_iter()
+            .map(|p| p.0)
+            .collect())
+    }
+}
+
+#[cfg(unix)]
+#[allow(clippy::unnecessary_wraps)]
+fn to_raw(path: &Path) -> Option<Vec<u8>> {
+    use std::os::unix::ffi::OsStrExt;
+    Some(path.as_os_str().as_bytes().to_vec())
+}
+
+#[cfg(not(unix))]
+const fn to_raw(_path: &Path) -> Option<Vec<u8>> {
+    None
+}
+
+#[cfg(unix)]
+#[allow(clippy::unnecessary_wraps)]
+fn from_raw(raw: Vec<u8>) -> Option<PathBuf> {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+    Some(PathBuf::from(OsString::from_vec(raw)))
+}
+
+#[cfg(not(unix))]
+#[allow(clippy::needless_pass_by_value)]
+const fn from_raw(_raw: Vec<u8>) -> Option<PathBuf> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use serde::{Deserialize, Serialize};
+    use std::path::PathBuf;
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Record {
+        #[serde(with = "super")]
+        path: PathBuf,
+    }
+
+    #[test]
+    fn text_round_trip() -> Result<()> {
+        let record = Record {
+            path: PathBuf::from("/home/user/project"),
+        };
+        let yaml_str = serde_yaml::to_string(&record)?;
+        assert_eq!("path: /home/user/project\n", yaml_str);
+        assert_eq!(record, serde_yaml::from_str::<Record>(&yaml_str)?);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn raw_round_trip() -> Result<()> {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        let record = Record {
+            path: PathBuf::from(OsStr::from_bytes(b"/caf\xe9")),
+        };
+        let yaml_str = serde_yaml::to_string(&record)?;
+        assert_eq!(record, serde_yaml::from_str::<Record>(&yaml_str)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+// Copyright (c) 2023 Richard Cook
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+use crate::error::RepoError;
+use crate::link::Link;
+use crate::meta_id::MetaId;
+use crate::result::RepoResult;
+use crate::schema::{read_versioned, SchemaKind, CURRENT_SCHEMA_VERSION};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ProjectMarkerRecord {
+    #[serde(default)]
+    pub(crate) schema_version: u32,
+    pub(crate) meta_id: MetaId,
+}
+
+impl ProjectMarkerRecord {
+    pub(crate) fn read(marker_path: &Path) -> RepoResult<Option<Self>> {
+        if !marker_path.is_file() {
+            return Ok(None);
+        }
+
+        Ok(Some(read_versioned(
+            SchemaKind::ProjectMarker,
+            marker_path,
+        )?))
+    }
+
+    pub(crate) fn to_yaml(meta_id: &MetaId) -> RepoResult<String> {
+        let record = Self {
+            schema_version: CURRENT_SCHEMA_VERSION,
+            meta_id: meta_id.clone(),
+        };
+        serde_yaml::to_string(&record).map_err(RepoError::other)
+    }
+
+    pub(crate) fn matches(marker_path: &Path, meta_id: &MetaId) -> bool {
+        Self::read(marker_path).is_ok_and(|record| record.is_some_and(|r| r.meta_id == *meta_id))
+    }
+}
+
+#[derive(Debug)]
+pub struct MovedProject {
+    project_dir: PathBuf,
+    meta_id: MetaId,
+    previous_links: Vec<Link>,
+    is_copy: bool,
+}
+
+impl MovedProject {
+    pub(crate) const fn new(
+        project_dir: PathBuf,
+        meta_id: MetaId,
+        previous_links: Vec<Link>,
+        is_copy: bool,
+    ) -> Self {
+        Self {
+            project_dir,
+            meta_id,
+            previous_links,
+            is_copy,
+        }
+    }
+
+    #[must_use]
+    pub fn project_dir(&self) -> &Path {
+        &self.project_dir
+    }
+
+    #[must_use]
+    pub const fn meta_id(&self) -> &MetaId {
+        &self.meta_id
+    }
+
+    #[must_use]
+    pub fn previous_links(&self) -> &[Link] {
+        &self.previous_links
+    }
+
+    // The project directory that wrote the marker still exists, so this
+    // directory was copied rather than moved
+    #[must_use]
+    pub const fn is_copy(&self) -> bool {
+        self.is_copy
+    }
+}
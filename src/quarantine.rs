@@ -0,0 +1,229 @@
+// Copyright (c) 2023 Richard Cook
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+use crate::data_dir_lock::DataDirLock;
+use crate::error::RepoError;
+use crate::link::LinkRecord;
+use crate::manifest::Manifest;
+use crate::meta_id::MetaId;
+use crate::result::RepoResult;
+use crate::schema::{read_versioned_value, upgrade_value, SchemaKind, CURRENT_SCHEMA_VERSION};
+use crate::trash::TrashReason;
+use chrono::{DateTime, Duration, Utc};
+use joatmon::safe_write_file;
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::fs::{create_dir_all, read_dir, remove_dir_all, rename};
+use std::mem::take;
+use std::path::{Path, PathBuf};
+
+const DATA_DIR_NAME: &str = "data";
+const RECORD_FILE_NAME: &str = "quarantine.yaml";
+const LINKS_KEY: &str = "links";
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct QuarantineRecord {
+    #[serde(default)]
+    pub(crate) schema_version: u32,
+    pub(crate) quarantined_at: DateTime<Utc>,
+    pub(crate) meta_id: MetaId,
+    pub(crate) reason: TrashReason,
+    pub(crate) links: Vec<LinkRecord>,
+}
+
+#[derive(Clone, Debug)]
+pub struct QuarantineEntry {
+    dir: PathBuf,
+    record: QuarantineRecord,
+}
+
+impl QuarantineEntry {
+    #[must_use]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    #[must_use]
+    pub fn data_dir(&self) -> PathBuf {
+        self.dir.join(DATA_DIR_NAME)
+    }
+
+    #[must_use]
+    pub const fn quarantined_at(&self) -> &DateTime<Utc> {
+        &self.record.quarantined_at
+    }
+
+    #[must_use]
+    pub const fn meta_id(&self) -> &MetaId {
+        &self.record.meta_id
+    }
+
+    #[must_use]
+    pub const fn reason(&self) -> TrashReason {
+        self.record.reason
+    }
+
+    #[must_use]
+    pub fn project_dirs(&self) -> Vec<&Path> {
+        self.record
+            .links
+            .iter()
+            .map(|l| l.project_dir.as_path())
+            .collect()
+    }
+
+    pub(crate) fn links(&self) -> &[LinkRecord] {
+        &self.record.links
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Quarantine {
+    dir: PathBuf,
+    retention: Duration,
+}
+
+impl Quarantine {
+    pub(crate) fn new(dir: &Path, retention_days: u32) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            retention: Duration::days(i64::from(retention_days)),
+        }
+    }
+
+    pub(crate) fn put(
+        &self,
+        manifest: &Manifest,
+        links: Vec<LinkRecord>,
+        reason: TrashReason,
+    ) -> RepoResult<()> {
+        let (entry_dir, record_yaml) = self.prepare(manifest, links, reason)?;
+        Self::store(manifest.data_dir(), &entry_dir, &record_yaml)
+    }
+
+    pub(crate) fn prepare(
+        &self,
+        manifest: &Manifest,
+        links: Vec<LinkRecord>,
+        reason: TrashReason,
+    ) -> RepoResult<(PathBuf, String)> {
+        let record = QuarantineRecord {
+            schema_version: CURRENT_SCHEMA_VERSION,
+            quarantined_at: Utc::now(),
+            meta_id: manifest.meta_id().clone(),
+            reason,
+            links,
+        };
+        let yaml_str = serde_yaml::to_string(&record).map_err(RepoError::other)?;
+        Ok((self.make_entry_dir(manifest.meta_id()), yaml_str))
+    }
+
+    pub(crate) fn store(data_dir: &Path, entry_dir: &Path, record_yaml: &str) -> RepoResult<()> {
+        if !data_dir.exists() {
+            return Ok(());
+        }
+
+        if entry_dir.is_dir() {
+            remove_dir_all(entry_dir)
+                .map_err(|_e| RepoError::could_not_delete_directory(entry_dir))?;
+        }
+        create_dir_all(entry_dir).map_err(RepoError::other)?;
+        safe_write_file(&entry_dir.join(RECORD_FILE_NAME), record_yaml, false)
+            .map_err(RepoError::other)?;
+
+        DataDirLock::move_data_dir(data_dir, &entry_dir.join(DATA_DIR_NAME))
+    }
+
+    // Reverses a store that may or may not have completed: an entry
+    // directory is only removed when it holds no data directory
+    pub(crate) fn unstore(entry_dir: &Path, data_dir: &Path) -> RepoResult<()> {
+        let entry_data_dir = entry_dir.join(DATA_DIR_NAME);
+        if entry_data_dir.is_dir() && !data_dir.exists() {
+            rename(&entry_data_dir, data_dir).map_err(RepoError::other)?;
+        }
+        if entry_dir.is_dir() && !entry_data_dir.exists() {
+            remove_dir_all(entry_dir)
+                .map_err(|_e| RepoError::could_not_delete_directory(entry_dir))?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn get(&self, meta_id: &MetaId) -> RepoResult<Option<QuarantineEntry>> {
+        let entry_dir = self.make_entry_dir(meta_id);
+        if !entry_dir.is_dir() {
+            return Ok(None);
+        }
+        Ok(Some(Self::read_entry(&entry_dir)?))
+    }
+
+    pub(crate) fn list(&self) -> RepoResult<Vec<QuarantineEntry>> {
+        if !self.dir.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let mut entries = Vec::new();
+        for e in read_dir(&self.dir).map_err(RepoError::other)? {
+            let entry_dir = e.map_err(RepoError::other)?.path();
+            if entry_dir.is_dir() {
+                entries.push(Self::read_entry(&entry_dir)?);
+            }
+        }
+
+        entries.sort_by(|a, b| a.quarantined_at().cmp(b.quarantined_at()));
+        Ok(entries)
+    }
+
+    pub(crate) fn expire(&self, now: &DateTime<Utc>) -> RepoResult<Vec<QuarantineEntry>> {
+        let mut expired = Vec::new();
+        for entry in self.list()? {
+            if *entry.quarantined_at() + self.retention <= *now {
+                Self::remove(&entry)?;
+                expired.push(entry);
+            }
+        }
+        Ok(expired)
+    }
+
+    pub(crate) fn remove(entry: &QuarantineEntry) -> RepoResult<()> {
+        remove_dir_all(&entry.dir).map_err(|_e| RepoError::could_not_delete_directory(&entry.dir))
+    }
+
+    fn read_entry(entry_dir: &Path) -> RepoResult<QuarantineEntry> {
+        let record_path = entry_dir.join(RECORD_FILE_NAME);
+        let mut value = read_versioned_value(SchemaKind::Quarantine, &record_path)?;
+        // Link records keep the schema version they had when quarantined
+        if let Some(Value::Sequence(links)) = value.get_mut(LINKS_KEY) {
+            for link in links.iter_mut() {
+                *link = upgrade_value(SchemaKind::Link, &record_path, take(link))?;
+            }
+        }
+        let record = serde_yaml::from_value::<QuarantineRecord>(value)
+            .map_err(|e| RepoError::invalid_file(&record_path, &e))?;
+        Ok(QuarantineEntry {
+            dir: entry_dir.to_path_buf(),
+            record,
+        })
+    }
+
+    fn make_entry_dir(&self, meta_id: &MetaId) -> PathBuf {
+        self.dir.join(format!("{meta_id}"))
+    }
+}
@@ -0,0 +1,177 @@
+// Copyright (c) 2023 Richard Cook
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+use std::path::{Path, PathBuf};
+
+#[cfg(target_os = "linux")]
+const MOUNT_INFO_PATH: &str = "/proc/self/mountinfo";
+
+#[cfg(target_os = "linux")]
+const FSTAB_PATH: &str = "/etc/fstab";
+
+#[derive(Debug, Default)]
+pub struct Reachability {
+    protected_prefixes: Vec<PathBuf>,
+    mount_points: Option<Vec<PathBuf>>,
+    fstab_mount_points: Vec<PathBuf>,
+}
+
+impl Reachability {
+    pub(crate) fn probe(protected_prefixes: &[PathBuf]) -> Self {
+        let mut reachability = Self {
+            protected_prefixes: protected_prefixes.to_vec(),
+            ..Default::default()
+        };
+        reachability.probe_mounts();
+        reachability
+    }
+
+    #[cfg(target_os = "linux")]
+    fn probe_mounts(&mut self) {
+        use std::fs::read_to_string;
+
+        self.mount_points = read_to_string(MOUNT_INFO_PATH)
+            .ok()
+            .map(|s| parse_mount_info(&s));
+        self.fstab_mount_points = read_to_string(FSTAB_PATH)
+            .map(|s| parse_fstab(&s))
+            .unwrap_or_default();
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    #[allow(clippy::unused_self)]
+    const fn probe_mounts(&mut self) {}
+
+    pub(crate) fn is_unreachable(&self, project_dir: &Path) -> bool {
+        self.protected_prefixes
+            .iter()
+            .any(|p| project_dir.starts_with(p))
+            || self.is_on_absent_mount(project_dir)
+    }
+
+    // Removable media are only recognized through fstab entries: directories
+    // such as /media or /mnt must be listed in protected_prefixes to be
+    // treated as unreachable
+    fn is_on_absent_mount(&self, project_dir: &Path) -> bool {
+        let Some(mount_points) = &self.mount_points else {
+            return false;
+        };
+
+        self.fstab_mount_points
+            .iter()
+            .any(|m| project_dir.starts_with(m) && !mount_points.contains(m))
+    }
+}
+
+fn parse_mount_info(s: &str) -> Vec<PathBuf> {
+    s.lines()
+        .filter_map(|line| line.split_whitespace().nth(4))
+        .map(|field| PathBuf::from(unescape_mount_field(field)))
+        .collect()
+}
+
+fn parse_fstab(s: &str) -> Vec<PathBuf> {
+    s.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .filter(|field| field.starts_with('/') && *field != "/")
+        .map(|field| PathBuf::from(unescape_mount_field(field)))
+        .collect()
+}
+
+fn unescape_mount_field(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            let digits = chars.clone().take(3).collect::<String>();
+            if digits.len() == 3 {
+                if let Ok(value) = u8::from_str_radix(&digits, 8) {
+                    result.push(char::from(value));
+                    chars.nth(2);
+                    continue;
+                }
+            }
+        }
+        result.push(c);
+    }
+    result
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::{parse_fstab, parse_mount_info, unescape_mount_field, Reachability};
+    use rstest::rstest;
+    use std::path::{Path, PathBuf};
+
+    const MOUNT_INFO: &str = "\
+22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw
+23 22 0:21 / /proc rw,nosuid shared:12 - proc proc rw
+40 22 8:17 / /media/user/My\\040Disk rw,nosuid shared:30 - vfat /dev/sdb1 rw
+";
+
+    const FSTAB: &str = "\
+# <file system> <mount point> <type> <options> <dump> <pass>
+UUID=1234 / ext4 errors=remount-ro 0 1
+UUID=5678 /srv/backup ext4 defaults,nofail 0 2
+/swapfile none swap sw 0 0
+";
+
+    #[test]
+    fn parse_basics() {
+        assert_eq!(
+            vec![
+                PathBuf::from("/"),
+                PathBuf::from("/proc"),
+                PathBuf::from("/media/user/My Disk")
+            ],
+            parse_mount_info(MOUNT_INFO)
+        );
+        assert_eq!(vec![PathBuf::from("/srv/backup")], parse_fstab(FSTAB));
+        assert_eq!("a b\\x", unescape_mount_field("a\\040b\\x"));
+    }
+
+    #[rstest]
+    #[case(false, "/home/user/project")]
+    #[case(true, "/srv/backup/project")]
+    #[case(false, "/media/user/My Disk/project")]
+    #[case(false, "/media/user/Other Disk/project")]
+    #[case(false, "/mnt/usb/project")]
+    #[case(true, "/data/projects/project")]
+    fn is_unreachable(#[case] expected_result: bool, #[case] input: &str) {
+        let reachability = Reachability {
+            protected_prefixes: vec![PathBuf::from("/data")],
+            mount_points: Some(parse_mount_info(MOUNT_INFO)),
+            fstab_mount_points: parse_fstab(FSTAB),
+        };
+        assert_eq!(
+            expected_result,
+            reachability.is_unreachable(Path::new(input))
+        );
+    }
+
+    #[test]
+    fn no_mount_info() {
+        let reachability = Reachability::default();
+        assert!(!reachability.is_unreachable(Path::new("/mnt/usb/project")));
+    }
+}
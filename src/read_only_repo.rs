@@ -0,0 +1,468 @@
+// Copyright (c) 2023 Richard Cook
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+use crate::archive::{write_archive, ExportOptions};
+use crate::check::{check, CheckProblem};
+use crate::config::RepoConfig;
+use crate::data_dir_lock::DataDirLock;
+use crate::dir_info::DirInfo;
+use crate::disk_usage::UsageReport;
+use crate::error::RepoError;
+use crate::link::{Link, LinkRecord};
+use crate::link_hash_algorithm::LinkHashAlgorithm;
+use crate::link_id::LinkId;
+use crate::lock::{LockMode, RepoLock};
+use crate::manifest::{Manifest, ManifestRecord};
+use crate::meta_id::MetaId;
+use crate::meta_ref::resolve_meta_ref;
+use crate::path_policy::PathPolicy;
+use crate::project_marker::{MovedProject, ProjectMarkerRecord};
+use crate::quarantine::Quarantine;
+use crate::result::RepoResult;
+use crate::schema::{from_versioned_value, read_versioned, SchemaKind};
+use crate::shared_path::SharedPath;
+use crate::trash_policy::TrashPolicy;
+use chrono::Utc;
+use joatmon::{read_text_file, read_yaml_file, FileReadError, HasOtherError};
+use log::warn;
+use path_absolutize::Absolutize;
+use std::fs::read_dir;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub const MANIFEST_FILE_NAME: &str = "manifest.yaml";
+
+#[derive(Debug)]
+pub struct ReadOnlyRepo {
+    config: RepoConfig,
+    _lock: RepoLock,
+}
+
+impl ReadOnlyRepo {
+    pub fn new(config: RepoConfig) -> RepoResult<Option<Self>> {
+        Self::try_open(config, LockMode::Shared)
+    }
+
+    pub fn new_blocking(config: RepoConfig) -> RepoResult<Self> {
+        Self::open_blocking(config, LockMode::Shared)
+    }
+
+    pub fn new_with_timeout(config: RepoConfig, timeout: Duration) -> RepoResult<Self> {
+        Self::open_with_timeout(config, LockMode::Shared, timeout)
+    }
+
+    pub(crate) fn try_open(config: RepoConfig, mode: LockMode) -> RepoResult<Option<Self>> {
+        let mut lock = RepoLock::open(&config.lock_path)?;
+        Ok(if lock.try_lock(mode)? {
+            Some(Self {
+                config,
+                _lock: lock,
+            })
+        } else {
+            None
+        })
+    }
+
+    pub(crate) fn open_blocking(config: RepoConfig, mode: LockMode) -> RepoResult<Self> {
+        let mut lock = RepoLock::open(&config.lock_path)?;
+        lock.lock(mode)?;
+        Ok(Self {
+            config,
+            _lock: lock,
+        })
+    }
+
+    pub(crate) fn open_with_timeout(
+        config: RepoConfig,
+        mode: LockMode,
+        timeout: Duration,
+    ) -> RepoResult<Self> {
+        let mut lock = RepoLock::open(&config.lock_path)?;
+        lock.lock_with_timeout(mode, timeout)?;
+        Ok(Self {
+            config,
+            _lock: lock,
+        })
+    }
+
+    #[must_use]
+    pub fn lock_path(&self) -> &Path {
+        &self.config.lock_path
+    }
+
+    #[must_use]
+    pub fn config_path(&self) -> &Path {
+        &self.config.config_path
+    }
+
+    #[must_use]
+    pub fn links_dir(&self) -> &Path {
+        &self.config.links_dir
+    }
+
+    #[must_use]
+    pub fn container_dir(&self) -> &Path {
+        &self.config.container_dir
+    }
+
+    #[must_use]
+    pub fn shared_dir(&self) -> &Path {
+        &self.config.shared_dir
+    }
+
+    #[must_use]
+    pub const fn link_hash_algorithm(&self) -> LinkHashAlgorithm {
+        self.config.link_hash_algorithm
+    }
+
+    #[must_use]
+    pub const fn path_policy(&self) -> PathPolicy {
+        self.config.path_policy
+    }
+
+    #[must_use]
+    pub fn project_marker(&self) -> Option<&str> {
+        self.config.project_marker.as_deref()
+    }
+
+    #[must_use]
+    pub const fn trash_policy(&self) -> &TrashPolicy {
+        &self.config.trash_policy
+    }
+
+    pub fn normalize_project_dir(&self, project_dir: &Path) -> RepoResult<PathBuf> {
+        self.config.path_policy.apply(project_dir)
+    }
+
+    pub fn list_links(&self) -> RepoResult<Vec<Link>> {
+        let mut links = Vec::new();
+
+        if self.config.links_dir.is_dir() {
+            for entry_opt in read_dir(&self.config.links_dir).map_err(RepoError::other)? {
+                let entry = entry_opt.map_err(RepoError::other)?;
+                if entry.path().is_file() {
+                    if let Some(link) = self.read_link_from_link_path(&entry.path())? {
+                        links.push(link);
+                    }
+                }
+            }
+        }
+
+        Ok(links)
+    }
+
+    pub fn list_manifests(&self) -> RepoResult<Vec<Manifest>> {
+        let mut manifests = Vec::new();
+
+        if self.config.container_dir.is_dir() {
+            for entry_opt in read_dir(&self.config.container_dir).map_err(RepoError::other)? {
+                let entry = entry_opt.map_err(RepoError::other)?;
+                if entry.path().is_dir() {
+                    manifests.push(self.read_manifest_from_datadir(&entry.path())?);
+                }
+            }
+        }
+
+        Ok(manifests)
+    }
+
+    pub fn resolve_meta_ref(&self, s: &str) -> RepoResult<MetaId> {
+        let manifests = self.list_manifests()?;
+        resolve_meta_ref(
+            s,
+            manifests
+                .iter()
+                .map(|m| (m.meta_id(), m.metadata().name.as_deref())),
+        )
+    }
+
+    pub fn check(&self) -> RepoResult<Vec<CheckProblem>> {
+        check(self)
+    }
+
+    pub fn usage_report(&self) -> RepoResult<UsageReport> {
+        UsageReport::compute(self.list_manifests()?, &self.config.shared_dir)
+    }
+
+    pub fn export<W>(
+        &self,
+        meta_id: &MetaId,
+        writer: W,
+        options: &ExportOptions,
+    ) -> RepoResult<Manifest>
+    where
+        W: Write,
+    {
+        let manifest = self.read_manifest(meta_id)?;
+        let _data_dir_lock = DataDirLock::try_acquire(manifest.data_dir())?.ok_or_else(|| {
+            RepoError::could_not_lock(&DataDirLock::make_lock_path(manifest.data_dir()))
+        })?;
+        write_archive(
+            writer,
+            &manifest,
+            &self.make_meta_shared_dir(meta_id),
+            options,
+        )?;
+        Ok(manifest)
+    }
+
+    pub fn get(&self, project_dir: &Path) -> RepoResult<Option<DirInfo>> {
+        let project_dir = self.normalize_project_dir(project_dir)?;
+        let (_, link_path) = self.locate_link(&project_dir)?;
+        if !link_path.is_file() {
+            return Ok(None);
+        }
+
+        self.read_dir_info(&link_path, &project_dir)
+            .map(Self::record_access)
+            .map(Some)
+    }
+
+    pub fn find(&self, dir: &Path) -> RepoResult<Option<DirInfo>> {
+        let dir = self.normalize_project_dir(dir)?;
+        let mut d = dir.as_path();
+        loop {
+            let (_, link_path) = self.locate_link(d)?;
+            if link_path.is_file() {
+                return self
+                    .read_dir_info(&link_path, d)
+                    .map(Self::record_access)
+                    .map(Some);
+            }
+
+            if let Some(p) = d.parent() {
+                d = p;
+            } else {
+                return Ok(None);
+            }
+        }
+    }
+
+    pub fn detect_move(&self, dir: &Path) -> RepoResult<Option<MovedProject>> {
+        let dir = self.normalize_project_dir(dir)?;
+        let mut d = dir.as_path();
+        loop {
+            if let Some(marker_path) = self.make_marker_path(d) {
+                match ProjectMarkerRecord::read(&marker_path) {
+                    Ok(Some(record)) => return self.detect_move_from_marker(d, record.meta_id),
+                    Ok(None) => {}
+                    Err(e) => warn!(
+                        "Ignoring unreadable project marker {}: {e}",
+                        marker_path.display()
+                    ),
+                }
+            }
+
+            if let Some(p) = d.parent() {
+                d = p;
+            } else {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn detect_move_from_marker(
+        &self,
+        project_dir: &Path,
+        meta_id: MetaId,
+    ) -> RepoResult<Option<MovedProject>> {
+        let (_, link_path) = self.locate_link(project_dir)?;
+        if link_path.is_file() || !self.make_data_dir(&meta_id).is_dir() {
+            return Ok(None);
+        }
+
+        let (previous_links, live_links): (Vec<_>, Vec<_>) = self
+            .list_links()?
+            .into_iter()
+            .filter(|l| *l.meta_id() == meta_id)
+            .partition(|l| !l.project_dir().is_dir());
+        let is_copy = previous_links.is_empty() && !live_links.is_empty();
+
+        Ok(Some(MovedProject::new(
+            project_dir.to_path_buf(),
+            meta_id,
+            previous_links,
+            is_copy,
+        )))
+    }
+
+    pub(crate) fn record_access(mut dir_info: DirInfo) -> DirInfo {
+        dir_info.manifest.record_access(Utc::now());
+        dir_info
+    }
+
+    fn read_dir_info(&self, link_path: &Path, project_dir: &Path) -> RepoResult<DirInfo> {
+        let link_record = read_versioned::<LinkRecord>(SchemaKind::Link, link_path)?;
+        if link_record.project_dir != project_dir {
+            return Err(RepoError::invalid_link_file(
+                link_path,
+                &link_record.project_dir,
+                project_dir,
+            ));
+        }
+
+        let data_dir = self.make_data_dir(&link_record.meta_id);
+        let manifest_path = data_dir.join(MANIFEST_FILE_NAME);
+        let manifest_record =
+            read_versioned::<ManifestRecord>(SchemaKind::Manifest, &manifest_path)?;
+
+        Ok(DirInfo {
+            manifest: Manifest::new(data_dir, manifest_path, manifest_record),
+            link: Link::new(link_path.to_path_buf(), link_record),
+        })
+    }
+
+    pub fn read_manifest(&self, meta_id: &MetaId) -> RepoResult<Manifest> {
+        let manifest_path = self.make_data_dir(meta_id);
+        self.read_manifest_from_datadir(&manifest_path)
+    }
+
+    pub fn read_manifest_from_datadir(&self, data_dir: &Path) -> RepoResult<Manifest> {
+        let manifest_path = data_dir.join(MANIFEST_FILE_NAME);
+        let record = read_versioned(SchemaKind::Manifest, &manifest_path)?;
+        Ok(Manifest::new(data_dir.to_path_buf(), manifest_path, record))
+    }
+
+    pub fn read_link(&self, project_dir: &Path) -> RepoResult<Option<Link>> {
+        let project_dir = self.normalize_project_dir(project_dir)?;
+        let (_, link_path) = self.locate_link(&project_dir)?;
+        self.read_link_from_link_path(&link_path)
+    }
+
+    pub fn read_link_from_link_path(&self, link_path: &Path) -> RepoResult<Option<Link>> {
+        match read_yaml_file(link_path) {
+            Ok(value) => Ok(Some(Link::new(
+                link_path.to_path_buf(),
+                from_versioned_value(SchemaKind::Link, link_path, value)?,
+            ))),
+            Err(e)
+                if e.downcast_other_ref::<FileReadError>()
+                    .is_some_and(FileReadError::is_not_found) =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(RepoError::other(e)),
+        }
+    }
+
+    pub fn read_shared_file(&self, path: &SharedPath) -> RepoResult<Option<String>> {
+        let p = self.resolve_shared_path(path)?;
+        Ok(match read_text_file(&p) {
+            Ok(s) => Some(s),
+            Err(e) if e.is_not_found() => None,
+            Err(e) => return Err(RepoError::other(e)),
+        })
+    }
+
+    pub fn lock_data_dir(&self, meta_id: &MetaId) -> RepoResult<Option<DataDirLock>> {
+        DataDirLock::try_acquire(&self.existing_data_dir(meta_id)?)
+    }
+
+    pub fn lock_data_dir_blocking(&self, meta_id: &MetaId) -> RepoResult<DataDirLock> {
+        DataDirLock::acquire_blocking(&self.existing_data_dir(meta_id)?)
+    }
+
+    pub fn lock_data_dir_with_timeout(
+        &self,
+        meta_id: &MetaId,
+        timeout: Duration,
+    ) -> RepoResult<DataDirLock> {
+        DataDirLock::acquire_with_timeout(&self.existing_data_dir(meta_id)?, timeout)
+    }
+
+    pub(crate) const fn config(&self) -> &RepoConfig {
+        &self.config
+    }
+
+    pub(crate) fn make_link_id(&self, project_dir: &Path) -> RepoResult<LinkId> {
+        LinkId::compute(project_dir, self.config.link_hash_algorithm)
+    }
+
+    pub(crate) fn locate_link(&self, project_dir: &Path) -> RepoResult<(LinkId, PathBuf)> {
+        let link_id = self.make_link_id(project_dir)?;
+        let link_path = self.make_link_path(&link_id);
+        if link_path.is_file() {
+            return Ok((link_id, link_path));
+        }
+
+        for algorithm in LinkHashAlgorithm::ALL {
+            if algorithm != self.config.link_hash_algorithm {
+                let other_link_id = LinkId::compute(project_dir, algorithm)?;
+                let other_link_path = self.make_link_path(&other_link_id);
+                if other_link_path.is_file() {
+                    return Ok((other_link_id, other_link_path));
+                }
+            }
+        }
+
+        Ok((link_id, link_path))
+    }
+
+    pub(crate) fn make_link_path(&self, link_id: &LinkId) -> PathBuf {
+        self.config.links_dir.join(format!("{link_id}.yaml"))
+    }
+
+    pub(crate) fn make_data_dir(&self, meta_id: &MetaId) -> PathBuf {
+        self.config.container_dir.join(format!("{meta_id}"))
+    }
+
+    // Shared files belonging to a single metadirectory live under a
+    // subdirectory of the shared directory named after its meta ID
+    pub(crate) fn make_meta_shared_dir(&self, meta_id: &MetaId) -> PathBuf {
+        self.config.shared_dir.join(format!("{meta_id}"))
+    }
+
+    // Only lock metadirectories that exist so that arbitrary IDs do not
+    // leave lock files behind in the container directory
+    fn existing_data_dir(&self, meta_id: &MetaId) -> RepoResult<PathBuf> {
+        let data_dir = self.make_data_dir(meta_id);
+        if !data_dir.is_dir() {
+            return Err(RepoError::meta_ref_not_found(&format!("{meta_id}")));
+        }
+        Ok(data_dir)
+    }
+
+    pub(crate) fn quarantine(&self) -> Option<Quarantine> {
+        self.config
+            .quarantine_dir
+            .as_ref()
+            .map(|dir| Quarantine::new(dir, self.config.quarantine_retention_days))
+    }
+
+    pub(crate) fn make_marker_path(&self, project_dir: &Path) -> Option<PathBuf> {
+        self.config
+            .project_marker
+            .as_ref()
+            .map(|file_name| project_dir.join(file_name))
+    }
+
+    pub(crate) fn resolve_shared_path(&self, path: &SharedPath) -> RepoResult<PathBuf> {
+        let p = Path::new(path.as_str())
+            .absolutize_from(&self.config.shared_dir)
+            .map_err(RepoError::other)?
+            .into_owned();
+        if !p.starts_with(&self.config.shared_dir) {
+            return Err(RepoError::invalid_shared_path(path));
+        }
+        Ok(p)
+    }
+}
@@ -19,135 +19,194 @@
 // OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
 // WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 //
+use crate::access_stats::AccessStats;
+use crate::archive::{unpack_archive, ArchiveCompression, ExportOptions, ImportOptions};
+use crate::backup::write_backup;
+use crate::check::CheckProblem;
 use crate::config::RepoConfig;
+use crate::data_dir_lock::DataDirLock;
 use crate::dir_info::DirInfo;
+use crate::disk_usage::UsageReport;
 use crate::error::RepoError;
+use crate::journal::{Journal, JournalAction};
 use crate::link::{Link, LinkRecord};
+use crate::link_hash_algorithm::LinkHashAlgorithm;
 use crate::link_id::LinkId;
+use crate::lock::{LockMode, RepoLock};
 use crate::manifest::{Manifest, ManifestRecord};
 use crate::meta_id::MetaId;
+use crate::meta_ref::validate_meta_name;
+use crate::metadata::{ExtraFields, Metadata};
+use crate::path_policy::PathPolicy;
+use crate::project_marker::{MovedProject, ProjectMarkerRecord};
+use crate::read_only_repo::{ReadOnlyRepo, MANIFEST_FILE_NAME};
+use crate::removal::Removal;
 use crate::result::RepoResult;
+use crate::schema::{migrate_file, read_versioned, SchemaKind, CURRENT_SCHEMA_VERSION};
 use crate::shared_path::SharedPath;
-use crate::trash::Trash;
+use crate::trash::{Trash, TrashReason};
+use crate::trash_policy::TrashPolicy;
 use chrono::Utc;
-use fslock::LockFile;
-use joatmon::{read_text_file, read_yaml_file, safe_write_file, FileReadError, HasOtherError};
-use path_absolutize::Absolutize;
-use std::fs::{read_dir, remove_dir_all, remove_file};
+use joatmon::safe_write_file;
+use std::fs::{create_dir_all, read_dir, remove_dir_all, remove_file, rename, File};
+use std::io::{BufWriter, Read, Write};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-const MANIFEST_FILE_NAME: &str = "manifest.yaml";
+const IMPORT_STAGING_PREFIX: &str = ".import-";
 
 #[derive(Debug)]
 pub struct Repo {
-    config: RepoConfig,
-    _lock_file: LockFile,
+    inner: ReadOnlyRepo,
 }
 
 impl Repo {
     pub fn new(config: RepoConfig) -> RepoResult<Option<Self>> {
-        safe_write_file(&config.lock_path, vec![], true).map_err(RepoError::other)?;
-        let mut lock_file = LockFile::open(&config.lock_path)
-            .map_err(|_e| RepoError::could_not_open_lock_file(&config.lock_path))?;
-        Ok(
-            if lock_file
-                .try_lock_with_pid()
-                .map_err(|_e| RepoError::could_not_lock(&config.lock_path))?
+        ReadOnlyRepo::try_open(config, LockMode::Exclusive)?
+            .map(Self::from_inner)
+            .transpose()
+    }
+
+    pub fn new_blocking(config: RepoConfig) -> RepoResult<Self> {
+        Self::from_inner(ReadOnlyRepo::open_blocking(config, LockMode::Exclusive)?)
+    }
+
+    pub fn new_with_timeout(config: RepoConfig, timeout: Duration) -> RepoResult<Self> {
+        Self::from_inner(ReadOnlyRepo::open_with_timeout(
+            config,
+            LockMode::Exclusive,
+            timeout,
+        )?)
+    }
+
+    fn from_inner(inner: ReadOnlyRepo) -> RepoResult<Self> {
+        let repo = Self { inner };
+        repo.journal().replay()?;
+        repo.remove_import_staging_dirs();
+        Ok(repo)
+    }
+
+    // Staging directories are left behind if a process dies mid-import:
+    // any that remain once the journal has been replayed are garbage
+    fn remove_import_staging_dirs(&self) {
+        let Ok(entries) = read_dir(self.container_dir()) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            if entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with(IMPORT_STAGING_PREFIX)
             {
-                Some(Self {
-                    config,
-                    _lock_file: lock_file,
-                })
-            } else {
-                None
-            },
-        )
+                _ = remove_dir_all(entry.path());
+            }
+        }
+    }
+
+    #[must_use]
+    pub const fn as_read_only(&self) -> &ReadOnlyRepo {
+        &self.inner
     }
 
     #[must_use]
     pub fn lock_path(&self) -> &Path {
-        &self.config.lock_path
+        self.inner.lock_path()
     }
 
     #[must_use]
     pub fn config_path(&self) -> &Path {
-        &self.config.config_path
+        self.inner.config_path()
     }
 
     #[must_use]
     pub fn links_dir(&self) -> &Path {
-        &self.config.links_dir
+        self.inner.links_dir()
     }
 
     #[must_use]
     pub fn container_dir(&self) -> &Path {
-        &self.config.container_dir
+        self.inner.container_dir()
     }
 
     #[must_use]
     pub fn shared_dir(&self) -> &Path {
-        &self.config.shared_dir
+        self.inner.shared_dir()
     }
 
-    pub fn list_links(&self) -> RepoResult<Vec<Link>> {
-        let mut links = Vec::new();
+    #[must_use]
+    pub const fn link_hash_algorithm(&self) -> LinkHashAlgorithm {
+        self.inner.link_hash_algorithm()
+    }
 
-        if self.config.links_dir.is_dir() {
-            for entry_opt in read_dir(&self.config.links_dir).map_err(RepoError::other)? {
-                let entry = entry_opt.map_err(RepoError::other)?;
-                if entry.path().is_file() {
-                    if let Some(link) = self.read_link_from_link_path(&entry.path())? {
-                        links.push(link);
-                    }
-                }
-            }
-        }
+    #[must_use]
+    pub const fn path_policy(&self) -> PathPolicy {
+        self.inner.path_policy()
+    }
 
-        Ok(links)
+    #[must_use]
+    pub fn project_marker(&self) -> Option<&str> {
+        self.inner.project_marker()
     }
 
-    pub fn list_manifests(&self) -> RepoResult<Vec<Manifest>> {
-        let mut manifests = Vec::new();
+    #[must_use]
+    pub const fn trash_policy(&self) -> &TrashPolicy {
+        self.inner.trash_policy()
+    }
 
-        if self.config.container_dir.is_dir() {
-            for entry_opt in read_dir(&self.config.container_dir).map_err(RepoError::other)? {
-                let entry = entry_opt.map_err(RepoError::other)?;
-                if entry.path().is_dir() {
-                    manifests.push(self.read_manifest_from_datadir(&entry.path())?);
-                }
-            }
-        }
+    pub fn normalize_project_dir(&self, project_dir: &Path) -> RepoResult<PathBuf> {
+        self.inner.normalize_project_dir(project_dir)
+    }
+
+    pub fn list_links(&self) -> RepoResult<Vec<Link>> {
+        self.inner.list_links()
+    }
 
-        Ok(manifests)
+    pub fn list_manifests(&self) -> RepoResult<Vec<Manifest>> {
+        self.inner.list_manifests()
     }
 
     pub fn init(&self, project_dir: &Path) -> RepoResult<Option<DirInfo>> {
-        let link_id = Self::make_link_id(project_dir)?;
-        let link_path = self.make_link_path(&link_id);
+        let project_dir = self.normalize_project_dir(project_dir)?;
+        let (link_id, link_path) = self.inner.locate_link(&project_dir)?;
         if link_path.is_file() {
             return Ok(None);
         }
 
         let meta_id = MetaId::random();
-        let data_dir = self.make_data_dir(&meta_id);
+        let data_dir = self.inner.make_data_dir(&meta_id);
         let manifest_path = data_dir.join(MANIFEST_FILE_NAME);
 
         let manifest_record = ManifestRecord {
+            schema_version: CURRENT_SCHEMA_VERSION,
             created_at: Utc::now(),
-            original_project_dir: project_dir.to_path_buf(),
+            original_project_dir: project_dir.clone(),
             meta_id: meta_id.clone(),
+            metadata: Metadata::default(),
+            extra: ExtraFields::default(),
         };
-        let yaml_str = serde_yaml::to_string(&manifest_record).map_err(RepoError::other)?;
-        safe_write_file(&manifest_path, yaml_str, false).map_err(RepoError::other)?;
-
         let link_record = LinkRecord {
+            schema_version: CURRENT_SCHEMA_VERSION,
             created_at: Utc::now(),
+            hash_algorithm: link_id.algorithm(),
             link_id,
-            project_dir: project_dir.to_path_buf(),
+            project_dir,
             meta_id,
+            metadata: Metadata::default(),
+            extra: ExtraFields::default(),
         };
-        let yaml_str = serde_yaml::to_string(&link_record).map_err(RepoError::other)?;
-        safe_write_file(&link_path, yaml_str, false).map_err(RepoError::other)?;
+
+        let mut actions = vec![
+            JournalAction::WriteFile {
+                path: manifest_path.clone(),
+                contents: serde_yaml::to_string(&manifest_record).map_err(RepoError::other)?,
+            },
+            JournalAction::WriteFile {
+                path: link_path.clone(),
+                contents: serde_yaml::to_string(&link_record).map_err(RepoError::other)?,
+            },
+        ];
+        actions.extend(self.write_marker_action(&link_record.project_dir, &link_record.meta_id)?);
+        self.journal().run("init", actions)?;
 
         Ok(Some(DirInfo {
             manifest: Manifest::new(data_dir, manifest_path, manifest_record),
@@ -155,157 +214,1112 @@ impl Repo {
         }))
     }
 
-    pub fn remove(&self, project_dir: &Path) -> RepoResult<bool> {
-        Ok(if let Some(dir_info) = self.get(project_dir)? {
-            remove_file(dir_info.link_path())
-                .map_err(|_e| RepoError::could_not_delete_file(&self.config.config_path))?;
-            Trash::compute(self)?.empty()?;
-            true
-        } else {
-            false
-        })
+    pub fn remove(&self, project_dir: &Path) -> RepoResult<Option<Removal>> {
+        let Some(dir_info) = self.inner.get(project_dir)? else {
+            return Ok(None);
+        };
+
+        let is_referenced = self
+            .list_links()?
+            .iter()
+            .any(|l| l.link_path() != dir_info.link_path() && l.meta_id() == dir_info.meta_id());
+
+        let mut actions = self.unlink_actions(&dir_info);
+        if !is_referenced {
+            let data_dir = dir_info.data_dir().to_path_buf();
+            actions.push(match self.inner.quarantine() {
+                Some(quarantine) => {
+                    let links = vec![dir_info.link.record().clone()];
+                    let (entry_dir, record) =
+                        quarantine.prepare(&dir_info.manifest, links, TrashReason::Unreferenced)?;
+                    JournalAction::QuarantineDataDir {
+                        data_dir,
+                        entry_dir,
+                        record,
+                    }
+                }
+                None => JournalAction::RemoveDataDir { data_dir },
+            });
+        }
+        self.journal().run("remove", actions)?;
+
+        let mut removal = Removal::default();
+        if !is_referenced {
+            removal.manifests.push(dir_info.manifest);
+        }
+        removal.links.push(dir_info.link);
+
+        Ok(Some(removal))
     }
 
-    pub fn get(&self, project_dir: &Path) -> RepoResult<Option<DirInfo>> {
-        let link_id = Self::make_link_id(project_dir)?;
-        let link_path = self.make_link_path(&link_id);
-        if !link_path.is_file() {
+    pub fn remove_and_empty_trash(&self, project_dir: &Path) -> RepoResult<Option<Removal>> {
+        let Some(dir_info) = self.inner.get(project_dir)? else {
             return Ok(None);
+        };
+        self.journal()
+            .run("remove", self.unlink_actions(&dir_info))?;
+
+        let mut trash = Trash::compute(self)?;
+        let mut removal = Removal {
+            links: vec![dir_info.link],
+            manifests: Vec::new(),
+        };
+        removal
+            .links
+            .extend(trash.invalid_links.iter().map(|l| l.item.clone()));
+        removal
+            .manifests
+            .extend(trash.unreferenced_manifests.iter().map(|m| m.item.clone()));
+        trash.empty()?;
+
+        Ok(Some(removal))
+    }
+
+    pub fn resolve_meta_ref(&self, s: &str) -> RepoResult<MetaId> {
+        self.inner.resolve_meta_ref(s)
+    }
+
+    pub fn check(&self) -> RepoResult<Vec<CheckProblem>> {
+        self.inner.check()
+    }
+
+    pub fn repair(&self, problems: &[CheckProblem]) -> RepoResult<usize> {
+        let mut count = 0;
+        for repair in problems.iter().filter_map(CheckProblem::repair) {
+            repair.apply()?;
+            count += 1;
         }
+        Ok(count)
+    }
 
-        let link_record = read_yaml_file::<LinkRecord>(&link_path).map_err(RepoError::other)?;
-        if link_record.project_dir != *project_dir {
-            return Err(RepoError::invalid_link_file(
-                &link_path,
-                &link_record.project_dir,
-                project_dir,
-            ));
+    pub fn usage_report(&self) -> RepoResult<UsageReport> {
+        self.inner.usage_report()
+    }
+
+    pub fn export<W>(
+        &self,
+        meta_id: &MetaId,
+        writer: W,
+        options: &ExportOptions,
+    ) -> RepoResult<Manifest>
+    where
+        W: Write,
+    {
+        self.inner.export(meta_id, writer, options)
+    }
+
+    pub fn import<R>(
+        &self,
+        reader: R,
+        project_dir: &Path,
+        options: &ImportOptions,
+    ) -> RepoResult<Option<DirInfo>>
+    where
+        R: Read,
+    {
+        let project_dir = self.normalize_project_dir(project_dir)?;
+        let (link_id, link_path) = self.inner.locate_link(&project_dir)?;
+        if link_path.is_file() {
+            return Ok(None);
         }
 
-        let data_dir = self.make_data_dir(&link_record.meta_id);
+        let staging_dir = self
+            .container_dir()
+            .join(format!("{IMPORT_STAGING_PREFIX}{}", MetaId::random()));
+        create_dir_all(&staging_dir).map_err(RepoError::other)?;
+        let result = self.import_staged(
+            reader,
+            &staging_dir,
+            &project_dir,
+            link_id,
+            &link_path,
+            options,
+        );
+        _ = remove_dir_all(&staging_dir);
+        let (data_dir, manifest_record, link_record) = result?;
         let manifest_path = data_dir.join(MANIFEST_FILE_NAME);
-        let manifest_record =
-            read_yaml_file::<ManifestRecord>(&manifest_path).map_err(RepoError::other)?;
-
         Ok(Some(DirInfo {
             manifest: Manifest::new(data_dir, manifest_path, manifest_record),
             link: Link::new(link_path, link_record),
         }))
     }
 
+    pub fn get(&self, project_dir: &Path) -> RepoResult<Option<DirInfo>> {
+        self.inner.get(project_dir)
+    }
+
+    pub fn find(&self, dir: &Path) -> RepoResult<Option<DirInfo>> {
+        self.inner.find(dir)
+    }
+
     pub fn read_manifest(&self, meta_id: &MetaId) -> RepoResult<Manifest> {
-        let manifest_path = self.make_data_dir(meta_id);
-        self.read_manifest_from_datadir(&manifest_path)
+        self.inner.read_manifest(meta_id)
     }
 
     pub fn read_manifest_from_datadir(&self, data_dir: &Path) -> RepoResult<Manifest> {
-        let manifest_path = data_dir.join(MANIFEST_FILE_NAME);
-        let record = read_yaml_file(&manifest_path).map_err(RepoError::other)?;
-        Ok(Manifest::new(data_dir.to_path_buf(), manifest_path, record))
+        self.inner.read_manifest_from_datadir(data_dir)
     }
 
     pub fn read_link(&self, project_dir: &Path) -> RepoResult<Option<Link>> {
-        let link_id = Self::make_link_id(project_dir)?;
-        let link_path = self.make_link_path(&link_id);
-        self.read_link_from_link_path(&link_path)
+        self.inner.read_link(project_dir)
     }
 
     pub fn read_link_from_link_path(&self, link_path: &Path) -> RepoResult<Option<Link>> {
-        match read_yaml_file(link_path) {
-            Ok(link_record) => Ok(Some(Link::new(link_path.to_path_buf(), link_record))),
-            Err(e)
-                if e.downcast_other_ref::<FileReadError>()
-                    .map_or(false, FileReadError::is_not_found) =>
-            {
-                Ok(None)
-            }
-            Err(e) => Err(RepoError::other(e)),
-        }
+        self.inner.read_link_from_link_path(link_path)
     }
 
     pub fn link(&self, meta_id: &MetaId, project_dir: &Path) -> RepoResult<Option<DirInfo>> {
         let manifest = self.read_manifest(meta_id)?;
 
-        let link_id = Self::make_link_id(project_dir)?;
-        let link_path = self.make_link_path(&link_id);
+        let project_dir = self.normalize_project_dir(project_dir)?;
+        let (link_id, link_path) = self.inner.locate_link(&project_dir)?;
         if link_path.is_file() {
             return Ok(None);
         }
 
         let link_record = LinkRecord {
+            schema_version: CURRENT_SCHEMA_VERSION,
             created_at: Utc::now(),
+            hash_algorithm: link_id.algorithm(),
             link_id,
-            project_dir: project_dir.to_path_buf(),
+            project_dir,
             meta_id: meta_id.clone(),
+            metadata: Metadata::default(),
+            extra: ExtraFields::default(),
+        };
+
+        let mut actions = vec![JournalAction::WriteFile {
+            path: link_path.clone(),
+            contents: serde_yaml::to_string(&link_record).map_err(RepoError::other)?,
+        }];
+        actions.extend(self.write_marker_action(&link_record.project_dir, &link_record.meta_id)?);
+        self.journal().run("link", actions)?;
+
+        Ok(Some(ReadOnlyRepo::record_access(DirInfo {
+            manifest,
+            link: Link::new(link_path, link_record),
+        })))
+    }
+
+    pub fn set_manifest_metadata(
+        &self,
+        meta_id: &MetaId,
+        metadata: Metadata,
+    ) -> RepoResult<Manifest> {
+        let mut manifest = self.read_manifest(meta_id)?;
+        // Only validate names being changed so that metadata of manifests
+        // named before validation existed can still be updated
+        if metadata.name != manifest.metadata().name {
+            self.check_meta_name(meta_id, metadata.name.as_deref())?;
+        }
+        manifest.set_metadata(metadata)?;
+        Ok(manifest)
+    }
+
+    pub fn set_name(&self, meta_id: &MetaId, name: Option<&str>) -> RepoResult<Manifest> {
+        let mut metadata = self.read_manifest(meta_id)?.metadata().clone();
+        metadata.name = name.map(String::from);
+        self.set_manifest_metadata(meta_id, metadata)
+    }
+
+    fn check_meta_name(&self, meta_id: &MetaId, name: Option<&str>) -> RepoResult<()> {
+        if let Some(name) = name {
+            validate_meta_name(name)?;
+            if let Some(other) = self
+                .list_manifests()?
+                .iter()
+                .find(|m| m.meta_id() != meta_id && m.metadata().name.as_deref() == Some(name))
+            {
+                return Err(RepoError::duplicate_meta_name(name, other.meta_id()));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn set_link_metadata(
+        &self,
+        project_dir: &Path,
+        metadata: Metadata,
+    ) -> RepoResult<Option<Link>> {
+        let Some(link) = self.read_link(project_dir)? else {
+            return Ok(None);
+        };
+
+        let link_record = LinkRecord {
+            metadata,
+            ..link.record().clone()
         };
         let yaml_str = serde_yaml::to_string(&link_record).map_err(RepoError::other)?;
-        safe_write_file(&link_path, yaml_str, false).map_err(RepoError::other)?;
+        safe_write_file(link.link_path(), yaml_str, true).map_err(RepoError::other)?;
+
+        Ok(Some(Link::new(link.link_path().to_path_buf(), link_record)))
+    }
+
+    pub fn relink(
+        &self,
+        old_project_dir: &Path,
+        new_project_dir: &Path,
+    ) -> RepoResult<Option<DirInfo>> {
+        let Some(dir_info) = self.get(old_project_dir)? else {
+            return Ok(None);
+        };
+
+        let project_dir = self.normalize_project_dir(new_project_dir)?;
+        let (link_id, link_path) = self.inner.locate_link(&project_dir)?;
+        if link_path.is_file() {
+            return Ok(None);
+        }
+
+        let link_record = LinkRecord {
+            hash_algorithm: link_id.algorithm(),
+            link_id,
+            project_dir,
+            ..dir_info.link.record().clone()
+        };
+
+        let mut actions = vec![
+            JournalAction::WriteFile {
+                path: link_path.clone(),
+                contents: serde_yaml::to_string(&link_record).map_err(RepoError::other)?,
+            },
+            JournalAction::RemoveFile {
+                path: dir_info.link_path().to_path_buf(),
+            },
+        ];
+        actions.extend(self.write_marker_action(&link_record.project_dir, &link_record.meta_id)?);
+        self.journal().run("relink", actions)?;
 
         Ok(Some(DirInfo {
-            manifest,
+            manifest: dir_info.manifest,
             link: Link::new(link_path, link_record),
         }))
     }
 
+    pub fn detect_move(&self, dir: &Path) -> RepoResult<Option<MovedProject>> {
+        self.inner.detect_move(dir)
+    }
+
+    pub fn reconcile(&self, dir: &Path) -> RepoResult<Option<DirInfo>> {
+        let Some(moved_project) = self.detect_move(dir)? else {
+            return Ok(None);
+        };
+
+        if moved_project.is_copy() {
+            return Err(RepoError::project_copied(
+                moved_project.project_dir(),
+                moved_project.meta_id(),
+            ));
+        }
+
+        match moved_project.previous_links() {
+            [] => self.link(moved_project.meta_id(), moved_project.project_dir()),
+            [link] => self.relink(link.project_dir(), moved_project.project_dir()),
+            links => Err(RepoError::ambiguous_move(
+                moved_project.project_dir(),
+                &links.iter().map(Link::project_dir).collect::<Vec<_>>(),
+            )),
+        }
+    }
+
+    pub fn rehash_links(&self) -> RepoResult<Vec<Link>> {
+        let algorithm = self.link_hash_algorithm();
+        let mut links = Vec::new();
+
+        for link in self.list_links()? {
+            if link.link_id().algorithm() == algorithm {
+                continue;
+            }
+
+            let link_id = LinkId::compute(link.project_dir(), algorithm)?;
+            let link_path = self.inner.make_link_path(&link_id);
+            if link_path.is_file() {
+                continue;
+            }
+
+            let link_record = LinkRecord {
+                hash_algorithm: algorithm,
+                link_id,
+                ..link.record().clone()
+            };
+            let yaml_str = serde_yaml::to_string(&link_record).map_err(RepoError::other)?;
+            safe_write_file(&link_path, yaml_str, false).map_err(RepoError::other)?;
+            remove_file(link.link_path())
+                .map_err(|_e| RepoError::could_not_delete_file(link.link_path()))?;
+
+            links.push(Link::new(link_path, link_record));
+        }
+
+        Ok(links)
+    }
+
+    pub fn migrate(&self) -> RepoResult<Vec<PathBuf>> {
+        let mut migrated = Vec::new();
+
+        let config_path = self.config_path();
+        if config_path.is_file() && migrate_file::<RepoConfig>(SchemaKind::Config, config_path)? {
+            migrated.push(config_path.to_path_buf());
+        }
+
+        for manifest in self.list_manifests()? {
+            if migrate_file::<ManifestRecord>(SchemaKind::Manifest, manifest.manifest_path())? {
+                migrated.push(manifest.manifest_path().to_path_buf());
+            }
+        }
+
+        for link in self.list_links()? {
+            if migrate_file::<LinkRecord>(SchemaKind::Link, link.link_path())? {
+                migrated.push(link.link_path().to_path_buf());
+            }
+        }
+
+        Ok(migrated)
+    }
+
+    pub fn backup(&self, dest: &Path, compression: ArchiveCompression) -> RepoResult<usize> {
+        let config = self.inner.config();
+        let _data_dir_locks = DataDirLock::acquire_all(&config.container_dir)?;
+
+        let mut temp_path = dest.as_os_str().to_os_string();
+        temp_path.push(".tmp");
+        let temp_path = PathBuf::from(temp_path);
+        let result = File::create(&temp_path)
+            .map_err(RepoError::other)
+            .and_then(|file| write_backup(BufWriter::new(file), config, compression));
+        match result {
+            Ok(count) => {
+                rename(&temp_path, dest).map_err(RepoError::other)?;
+                Ok(count)
+            }
+            Err(e) => {
+                _ = remove_file(&temp_path);
+                Err(e)
+            }
+        }
+    }
+
+    pub fn relocate(self, new_base_dir: &Path) -> RepoResult<RepoConfig> {
+        if new_base_dir.is_dir()
+            && read_dir(new_base_dir)
+                .map_err(RepoError::other)?
+                .next()
+                .is_some()
+        {
+            return Err(RepoError::directory_not_empty(new_base_dir));
+        }
+
+        let config = self.inner.config().clone();
+        let new_config = config.rebase(new_base_dir);
+        let data_dir_locks = DataDirLock::acquire_all(&config.container_dir)?;
+        create_dir_all(new_base_dir).map_err(RepoError::other)?;
+
+        // Hold the lock at the new location and write the configuration before
+        // moving anything so that a concurrent open waits for the relocation
+        // instead of creating a default repository in its place
+        let mut new_lock = RepoLock::open(&new_config.lock_path)?;
+        if !new_lock.try_lock(LockMode::Exclusive)? {
+            return Err(RepoError::could_not_lock(&new_config.lock_path));
+        }
+        new_config.save(false)?;
+
+        let mut dirs = vec![
+            (&config.links_dir, &new_config.links_dir),
+            (&config.container_dir, &new_config.container_dir),
+            (&config.shared_dir, &new_config.shared_dir),
+        ];
+        if let (Some(dir), Some(new_dir)) = (&config.quarantine_dir, &new_config.quarantine_dir) {
+            dirs.push((dir, new_dir));
+        }
+
+        let mut moved = Vec::new();
+        let mut result = Ok(());
+        for (dir, new_dir) in dirs {
+            if dir != new_dir && dir.is_dir() {
+                if let Some(parent) = new_dir.parent() {
+                    result = create_dir_all(parent).map_err(RepoError::other);
+                }
+                if result.is_ok() {
+                    result = rename(dir, new_dir).map_err(RepoError::other);
+                }
+                if result.is_err() {
+                    break;
+                }
+                moved.push((dir, new_dir));
+            }
+        }
+        if result.is_ok() {
+            result = config.save_tombstone(new_base_dir);
+        }
+
+        if let Err(e) = result {
+            for (dir, new_dir) in moved.into_iter().rev() {
+                _ = rename(new_dir, dir);
+            }
+            _ = remove_file(&new_config.config_path);
+            return Err(e);
+        }
+
+        drop(new_lock);
+        drop(data_dir_locks);
+        drop(self);
+
+        Ok(new_config)
+    }
+
     pub fn purge(&self) -> RepoResult<()> {
-        if self.config.shared_dir.is_dir() {
-            remove_dir_all(&self.config.shared_dir)
-                .map_err(|_e| RepoError::could_not_delete_directory(&self.config.shared_dir))?;
+        let config = self.inner.config();
+        let data_dir_locks = DataDirLock::acquire_all(&config.container_dir)?;
+        if config.shared_dir.is_dir() {
+            remove_dir_all(&config.shared_dir)
+                .map_err(|_e| RepoError::could_not_delete_directory(&config.shared_dir))?;
+        }
+        if config.container_dir.is_dir() {
+            // Lock files cannot be deleted while held on all platforms, so
+            // delete the data directories under their locks first
+            for lock in &data_dir_locks {
+                remove_dir_all(lock.data_dir())
+                    .map_err(|_e| RepoError::could_not_delete_directory(lock.data_dir()))?;
+            }
+            drop(data_dir_locks);
+            remove_dir_all(&config.container_dir)
+                .map_err(|_e| RepoError::could_not_delete_directory(&config.container_dir))?;
         }
-        if self.config.container_dir.is_dir() {
-            remove_dir_all(&self.config.container_dir)
-                .map_err(|_e| RepoError::could_not_delete_directory(&self.config.container_dir))?;
+        if let Some(quarantine_dir) = config.quarantine_dir.as_ref().filter(|d| d.is_dir()) {
+            remove_dir_all(quarantine_dir)
+                .map_err(|_e| RepoError::could_not_delete_directory(quarantine_dir))?;
         }
-        if self.config.links_dir.is_dir() {
-            remove_dir_all(&self.config.links_dir)
-                .map_err(|_e| RepoError::could_not_delete_directory(&self.config.links_dir))?;
+        if config.links_dir.is_dir() {
+            remove_dir_all(&config.links_dir)
+                .map_err(|_e| RepoError::could_not_delete_directory(&config.links_dir))?;
         }
-        if self.config.config_path.is_file() {
-            remove_file(&self.config.config_path)
-                .map_err(|_e| RepoError::could_not_delete_file(&self.config.config_path))?;
+        if config.config_path.is_file() {
+            remove_file(&config.config_path)
+                .map_err(|_e| RepoError::could_not_delete_file(&config.config_path))?;
         }
-        if self.config.lock_path.is_file() {
-            remove_file(&self.config.lock_path)
-                .map_err(|_e| RepoError::could_not_delete_file(&self.config.lock_path))?;
+        if config.lock_path.is_file() {
+            remove_file(&config.lock_path)
+                .map_err(|_e| RepoError::could_not_delete_file(&config.lock_path))?;
         }
         Ok(())
     }
 
     pub fn read_shared_file(&self, path: &SharedPath) -> RepoResult<Option<String>> {
-        let p = self.resolve_shared_path(path)?;
-        Ok(match read_text_file(&p) {
-            Ok(s) => Some(s),
-            Err(e) if e.is_not_found() => None,
-            Err(e) => return Err(RepoError::other(e)),
-        })
+        self.inner.read_shared_file(path)
+    }
+
+    pub fn lock_data_dir(&self, meta_id: &MetaId) -> RepoResult<Option<DataDirLock>> {
+        self.inner.lock_data_dir(meta_id)
+    }
+
+    pub fn lock_data_dir_blocking(&self, meta_id: &MetaId) -> RepoResult<DataDirLock> {
+        self.inner.lock_data_dir_blocking(meta_id)
+    }
+
+    pub fn lock_data_dir_with_timeout(
+        &self,
+        meta_id: &MetaId,
+        timeout: Duration,
+    ) -> RepoResult<DataDirLock> {
+        self.inner.lock_data_dir_with_timeout(meta_id, timeout)
+    }
+
+    // The staged data directory is moved into place by the same journal
+    // run that writes the link so that a crash cannot leave it orphaned
+    fn import_staged<R>(
+        &self,
+        reader: R,
+        staging_dir: &Path,
+        project_dir: &Path,
+        link_id: LinkId,
+        link_path: &Path,
+        options: &ImportOptions,
+    ) -> RepoResult<(PathBuf, ManifestRecord, LinkRecord)>
+    where
+        R: Read,
+    {
+        let unpacked = unpack_archive(reader, staging_dir)?;
+        let manifest_path = unpacked.data_dir.join(MANIFEST_FILE_NAME);
+        let mut manifest_record =
+            read_versioned::<ManifestRecord>(SchemaKind::Manifest, &manifest_path)?;
+        if manifest_record.meta_id != unpacked.record.meta_id {
+            return Err(RepoError::invalid_archive(
+                "manifest does not match archive record",
+            ));
+        }
+
+        let meta_id = if options.new_meta_id {
+            MetaId::random()
+        } else {
+            manifest_record.meta_id.clone()
+        };
+        let data_dir = self.inner.make_data_dir(&meta_id);
+        if data_dir.exists() {
+            return Err(RepoError::duplicate_meta_id(&meta_id));
+        }
+
+        manifest_record.schema_version = CURRENT_SCHEMA_VERSION;
+        manifest_record.meta_id = meta_id;
+        let yaml_str = serde_yaml::to_string(&manifest_record).map_err(RepoError::other)?;
+        safe_write_file(&manifest_path, yaml_str, true).map_err(RepoError::other)?;
+        AccessStats::reset(&unpacked.data_dir)?;
+
+        let link_record = LinkRecord {
+            schema_version: CURRENT_SCHEMA_VERSION,
+            created_at: Utc::now(),
+            hash_algorithm: link_id.algorithm(),
+            link_id,
+            project_dir: project_dir.to_path_buf(),
+            meta_id: manifest_record.meta_id.clone(),
+            metadata: Metadata::default(),
+            extra: ExtraFields::default(),
+        };
+
+        let mut actions = vec![
+            JournalAction::MergeDir {
+                from: unpacked.shared_dir,
+                to: self.inner.make_meta_shared_dir(&link_record.meta_id),
+            },
+            JournalAction::MoveDir {
+                from: unpacked.data_dir,
+                to: data_dir.clone(),
+            },
+            JournalAction::WriteFile {
+                path: link_path.to_path_buf(),
+                contents: serde_yaml::to_string(&link_record).map_err(RepoError::other)?,
+            },
+        ];
+        actions.extend(self.write_marker_action(&link_record.project_dir, &link_record.meta_id)?);
+        self.journal().run("import", actions)?;
+
+        Ok((data_dir, manifest_record, link_record))
+    }
+
+    fn journal(&self) -> Journal {
+        Journal::new(self.config_path())
+    }
+
+    fn unlink_actions(&self, dir_info: &DirInfo) -> Vec<JournalAction> {
+        let mut actions = vec![JournalAction::RemoveFile {
+            path: dir_info.link_path().to_path_buf(),
+        }];
+        if let Some(marker_path) = self.inner.make_marker_path(dir_info.project_dir()) {
+            if ProjectMarkerRecord::matches(&marker_path, dir_info.meta_id()) {
+                actions.push(JournalAction::RemoveMarker { path: marker_path });
+            }
+        }
+        actions
+    }
+
+    fn write_marker_action(
+        &self,
+        project_dir: &Path,
+        meta_id: &MetaId,
+    ) -> RepoResult<Option<JournalAction>> {
+        let Some(marker_path) = self.inner.make_marker_path(project_dir) else {
+            return Ok(None);
+        };
+        if !project_dir.is_dir() {
+            return Ok(None);
+        }
+        Ok(Some(JournalAction::WriteMarker {
+            path: marker_path,
+            contents: ProjectMarkerRecord::to_yaml(meta_id)?,
+        }))
     }
 
     pub fn write_shared_file(&self, path: &SharedPath, value: &str) -> RepoResult<()> {
-        let p = self.resolve_shared_path(path)?;
+        let p = self.inner.resolve_shared_path(path)?;
         safe_write_file(&p, value, true).map_err(RepoError::other)?;
         Ok(())
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::RepoConfig;
+    use crate::meta_id::MetaId;
+    use crate::metadata::Metadata;
+    use anyhow::Result;
+    use std::collections::BTreeMap;
+    use std::fs::{
+        copy, create_dir_all, read_dir, read_to_string, remove_dir_all, remove_file, rename, write,
+    };
+    use tempdir::TempDir;
+
+    #[test]
+    fn find_basics() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let repo = RepoConfig::default(&temp_dir.path().join("repo"), None).repo_blocking()?;
+        let project_dir = temp_dir.path().join("project");
+        let sub_dir = project_dir.join("a").join("b");
+        create_dir_all(&sub_dir)?;
 
-    fn make_link_id(project_dir: &Path) -> RepoResult<LinkId> {
-        LinkId::try_from(project_dir)
+        assert!(repo.find(&sub_dir)?.is_none());
+
+        let dir_info = repo.init(&project_dir)?.expect("init must succeed");
+        let found = repo.find(&sub_dir)?.expect("find must succeed");
+        assert_eq!(dir_info.meta_id(), found.meta_id());
+        assert_eq!(project_dir, found.project_dir());
+        assert!(repo.find(&project_dir)?.is_some());
+        assert!(repo.find(temp_dir.path())?.is_none());
+        Ok(())
     }
 
-    fn make_link_path(&self, link_id: &LinkId) -> PathBuf {
-        self.config.links_dir.join(format!("{link_id}.yaml"))
+    #[test]
+    fn access_tracking() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let repo_dir = temp_dir.path().join("repo");
+        let repo = RepoConfig::default(&repo_dir, None).repo_blocking()?;
+        let project_dir = temp_dir.path().join("project");
+        let sub_dir = project_dir.join("a");
+        create_dir_all(&sub_dir)?;
+
+        let dir_info = repo.init(&project_dir)?.expect("init must succeed");
+        assert!(dir_info.last_accessed_at().is_none());
+        assert_eq!(0, dir_info.access_count());
+
+        let dir_info = repo.get(&project_dir)?.expect("get must succeed");
+        let last_accessed_at = *dir_info
+            .last_accessed_at()
+            .expect("last_accessed_at must be set");
+        assert_eq!(1, dir_info.access_count());
+
+        let dir_info = repo.find(&sub_dir)?.expect("find must succeed");
+        assert!(*dir_info.last_accessed_at().expect("must be set") >= last_accessed_at);
+        assert_eq!(2, dir_info.access_count());
+        drop(repo);
+
+        let read_only_repo = RepoConfig::default(&repo_dir, None).read_only_repo_blocking()?;
+        let dir_info = read_only_repo.get(&project_dir)?.expect("get must succeed");
+        assert_eq!(3, dir_info.access_count());
+
+        let manifest = read_only_repo.read_manifest(dir_info.meta_id())?;
+        assert_eq!(dir_info.last_accessed_at(), manifest.last_accessed_at());
+        assert_eq!(3, manifest.access_count());
+
+        // Failing to record access must not fail the lookup
+        remove_file(dir_info.data_dir().join("access.yaml"))?;
+        create_dir_all(dir_info.data_dir().join("access.yaml"))?;
+        let dir_info = read_only_repo.get(&project_dir)?.expect("get must succeed");
+        assert_eq!(0, dir_info.access_count());
+        Ok(())
     }
 
-    fn make_data_dir(&self, meta_id: &MetaId) -> PathBuf {
-        self.config.container_dir.join(format!("{meta_id}"))
+    #[test]
+    fn metadata_basics() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let repo = RepoConfig::default(&temp_dir.path().join("repo"), None).repo_blocking()?;
+        let project_dir = temp_dir.path().join("project");
+        let new_project_dir = temp_dir.path().join("new");
+
+        let dir_info = repo.init(&project_dir)?.expect("init must succeed");
+        assert!(dir_info.manifest_metadata().is_empty());
+        assert!(dir_info.link_metadata().is_empty());
+
+        let manifest_yaml = read_to_string(dir_info.manifest_path())?;
+        write(
+            dir_info.manifest_path(),
+            format!("{manifest_yaml}future_field: 123\n"),
+        )?;
+
+        let mut metadata = Metadata::default();
+        metadata.name = Some(String::from("Project"));
+        metadata.tags = vec![String::from("a"), String::from("b")];
+        metadata.values = BTreeMap::from([(String::from("key"), String::from("value"))]);
+        let manifest = repo.set_manifest_metadata(dir_info.meta_id(), metadata.clone())?;
+        assert_eq!(&metadata, manifest.metadata());
+        assert!(read_to_string(dir_info.manifest_path())?.contains("future_field: 123"));
+
+        assert!(repo
+            .set_link_metadata(&new_project_dir, metadata.clone())?
+            .is_none());
+        let link = repo
+            .set_link_metadata(&project_dir, metadata.clone())?
+            .expect("set_link_metadata must succeed");
+        assert_eq!(&metadata, link.metadata());
+
+        let moved = repo
+            .relink(&project_dir, &new_project_dir)?
+            .expect("relink must succeed");
+        assert_eq!(&metadata, moved.manifest_metadata());
+        assert_eq!(&metadata, moved.link_metadata());
+        Ok(())
     }
 
-    fn resolve_shared_path(&self, path: &SharedPath) -> RepoResult<PathBuf> {
-        let p = Path::new(path.as_str())
-            .absolutize_from(&self.config.shared_dir)
-            .map_err(RepoError::other)?
-            .into_owned();
-        if !p.starts_with(&self.config.shared_dir) {
-            return Err(RepoError::invalid_shared_path(path));
-        }
-        Ok(p)
+    #[test]
+    fn names() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let repo = RepoConfig::default(&temp_dir.path().join("repo"), None).repo_blocking()?;
+
+        let first = repo
+            .init(&temp_dir.path().join("first"))?
+            .expect("init must succeed");
+        let second = repo
+            .init(&temp_dir.path().join("second"))?
+            .expect("init must succeed");
+
+        repo.set_name(first.meta_id(), Some("project"))?;
+        assert_eq!(first.meta_id(), &repo.resolve_meta_ref("project")?);
+        assert_eq!(
+            second.meta_id(),
+            &repo.resolve_meta_ref(&second.meta_id().to_string())?
+        );
+        assert_eq!(
+            second.meta_id(),
+            &repo.resolve_meta_ref(&second.meta_id().to_string()[..12])?
+        );
+        assert!(repo
+            .set_name(second.meta_id(), Some("project"))
+            .expect_err("must fail")
+            .is_duplicate_meta_name());
+        assert!(repo
+            .set_name(second.meta_id(), Some(""))
+            .expect_err("must fail")
+            .is_invalid_meta_name());
+
+        let mut metadata = second.manifest_metadata().clone();
+        metadata.name = Some(String::from("project"));
+        assert!(repo
+            .set_manifest_metadata(second.meta_id(), metadata.clone())
+            .expect_err("must fail")
+            .is_duplicate_meta_name());
+        metadata.name = Some(String::from(" project"));
+        assert!(repo
+            .set_manifest_metadata(second.meta_id(), metadata)
+            .expect_err("must fail")
+            .is_invalid_meta_name());
+
+        repo.set_name(first.meta_id(), None)?;
+        assert!(repo
+            .resolve_meta_ref("project")
+            .expect_err("must fail")
+            .is_meta_ref_not_found());
+        Ok(())
+    }
+
+    #[test]
+    fn remove_basics() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let repo = RepoConfig::default(&temp_dir.path().join("repo"), None).repo_blocking()?;
+        let project_dir = temp_dir.path().join("project");
+        let other_project_dir = temp_dir.path().join("other");
+        let unrelated_project_dir = temp_dir.path().join("unrelated");
+
+        assert!(repo.remove(&project_dir)?.is_none());
+
+        let dir_info = repo.init(&project_dir)?.expect("init must succeed");
+        repo.link(dir_info.meta_id(), &other_project_dir)?
+            .expect("link must succeed");
+        let unrelated_dir_info = repo
+            .init(&unrelated_project_dir)?
+            .expect("init must succeed");
+
+        let removal = repo.remove(&project_dir)?.expect("remove must succeed");
+        assert_eq!(1, removal.links().len());
+        assert!(removal.manifests().is_empty());
+        assert!(dir_info.data_dir().is_dir());
+
+        let removal = repo
+            .remove(&other_project_dir)?
+            .expect("remove must succeed");
+        assert_eq!(1, removal.links().len());
+        assert_eq!(1, removal.manifests().len());
+        assert_eq!(dir_info.meta_id(), removal.manifests()[0].meta_id());
+        assert!(!dir_info.data_dir().exists());
+
+        assert!(unrelated_dir_info.data_dir().is_dir());
+        assert!(unrelated_dir_info.link_path().is_file());
+        Ok(())
+    }
+
+    #[test]
+    fn remove_and_empty_trash_basics() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let repo = RepoConfig::default(&temp_dir.path().join("repo"), None).repo_blocking()?;
+        let project_dir = temp_dir.path().join("project");
+        let unrelated_project_dir = temp_dir.path().join("unrelated");
+
+        repo.init(&project_dir)?.expect("init must succeed");
+        let unrelated_dir_info = repo
+            .init(&unrelated_project_dir)?
+            .expect("init must succeed");
+
+        let removal = repo
+            .remove_and_empty_trash(&project_dir)?
+            .expect("remove must succeed");
+        assert_eq!(2, removal.links().len());
+        assert_eq!(2, removal.manifests().len());
+        assert!(!unrelated_dir_info.data_dir().exists());
+        Ok(())
+    }
+
+    #[test]
+    fn relink_basics() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let repo = RepoConfig::default(&temp_dir.path().join("repo"), None).repo_blocking()?;
+        let old_project_dir = temp_dir.path().join("old");
+        let new_project_dir = temp_dir.path().join("new");
+
+        assert!(repo.relink(&old_project_dir, &new_project_dir)?.is_none());
+
+        let dir_info = repo.init(&old_project_dir)?.expect("init must succeed");
+        let moved = repo
+            .relink(&old_project_dir, &new_project_dir)?
+            .expect("relink must succeed");
+        assert_eq!(dir_info.meta_id(), moved.meta_id());
+        assert_eq!(dir_info.data_dir(), moved.data_dir());
+        assert_eq!(new_project_dir, moved.project_dir());
+        assert!(repo.get(&old_project_dir)?.is_none());
+        assert!(!dir_info.link_path().exists());
+        assert_eq!(
+            dir_info.meta_id(),
+            repo.get(&new_project_dir)?
+                .expect("get must succeed")
+                .meta_id()
+        );
+
+        repo.init(&old_project_dir)?;
+        assert!(repo.relink(&new_project_dir, &old_project_dir)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn reconcile_basics() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let mut config = RepoConfig::default(&temp_dir.path().join("repo"), None);
+        config.project_marker = Some(String::from(".marker"));
+        let repo = config.repo_blocking()?;
+        let old_project_dir = temp_dir.path().join("old");
+        let new_project_dir = temp_dir.path().join("new");
+        create_dir_all(&old_project_dir)?;
+
+        let dir_info = repo.init(&old_project_dir)?.expect("init must succeed");
+        assert!(old_project_dir.join(".marker").is_file());
+        assert!(repo.detect_move(&old_project_dir)?.is_none());
+
+        rename(&old_project_dir, &new_project_dir)?;
+        let moved_project = repo
+            .detect_move(&new_project_dir.join("sub"))?
+            .expect("move must be detected");
+        assert_eq!(new_project_dir, moved_project.project_dir());
+        assert_eq!(dir_info.meta_id(), moved_project.meta_id());
+        assert_eq!(1, moved_project.previous_links().len());
+
+        let reconciled = repo
+            .reconcile(&new_project_dir)?
+            .expect("reconcile must succeed");
+        assert_eq!(dir_info.meta_id(), reconciled.meta_id());
+        assert!(repo.get(&old_project_dir)?.is_none());
+        assert!(repo.get(&new_project_dir)?.is_some());
+        assert!(repo.detect_move(&new_project_dir)?.is_none());
+
+        assert!(repo.remove(&new_project_dir)?.is_some());
+        assert!(!new_project_dir.join(".marker").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn reconcile_ambiguous() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let mut config = RepoConfig::default(&temp_dir.path().join("repo"), None);
+        config.project_marker = Some(String::from(".marker"));
+        let repo = config.repo_blocking()?;
+        let first_project_dir = temp_dir.path().join("first");
+        let second_project_dir = temp_dir.path().join("second");
+        let new_project_dir = temp_dir.path().join("new");
+        create_dir_all(&first_project_dir)?;
+        create_dir_all(&second_project_dir)?;
+
+        let dir_info = repo.init(&first_project_dir)?.expect("init must succeed");
+        repo.link(dir_info.meta_id(), &second_project_dir)?
+            .expect("link must succeed");
+        rename(&first_project_dir, &new_project_dir)?;
+        remove_dir_all(&second_project_dir)?;
+
+        let moved_project = repo
+            .detect_move(&new_project_dir)?
+            .expect("must be detected");
+        assert_eq!(2, moved_project.previous_links().len());
+        assert!(repo
+            .reconcile(&new_project_dir)
+            .expect_err("must fail")
+            .is_ambiguous_move());
+        assert!(repo.get(&new_project_dir)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn reconcile_copy() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let mut config = RepoConfig::default(&temp_dir.path().join("repo"), None);
+        config.project_marker = Some(String::from(".marker"));
+        let repo = config.repo_blocking()?;
+        let project_dir = temp_dir.path().join("project");
+        let copy_dir = temp_dir.path().join("copy");
+        create_dir_all(&project_dir)?;
+        create_dir_all(&copy_dir)?;
+
+        repo.init(&project_dir)?.expect("init must succeed");
+        copy(project_dir.join(".marker"), copy_dir.join(".marker"))?;
+        let moved_project = repo.detect_move(&copy_dir)?.expect("must be detected");
+        assert!(moved_project.is_copy());
+        assert!(repo
+            .reconcile(&copy_dir)
+            .expect_err("must fail")
+            .is_project_copied());
+        assert!(repo.get(&copy_dir)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn malformed_marker() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let mut config = RepoConfig::default(&temp_dir.path().join("repo"), None);
+        config.project_marker = Some(String::from(".marker"));
+        let repo = config.repo_blocking()?;
+        let project_dir = temp_dir.path().join("project");
+        create_dir_all(&project_dir)?;
+
+        write(temp_dir.path().join(".marker"), "not: [valid")?;
+        assert!(repo.detect_move(&project_dir)?.is_none());
+        assert!(repo.find(&project_dir)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn relocate_basics() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let project_dir = temp_dir.path().join("project");
+        create_dir_all(&project_dir)?;
+        let old_base_dir = temp_dir.path().join("old");
+        let new_base_dir = temp_dir.path().join("new");
+
+        let repo = RepoConfig::default(&old_base_dir, None).repo_blocking()?;
+        let dir_info = repo.init(&project_dir)?.expect("must succeed");
+        write(dir_info.data_dir().join("file.txt"), "content")?;
+
+        let config = repo.relocate(&new_base_dir)?;
+        assert_eq!(new_base_dir.join("config.yaml"), config.config_path);
+        assert_eq!(new_base_dir.join("data"), config.container_dir);
+        assert!(!old_base_dir.join("data").exists());
+        assert!(old_base_dir.join(".lock").is_file());
+
+        // Opening the old location must not create a fresh repository
+        assert!(RepoConfig::default(&old_base_dir, None)
+            .repo_blocking()
+            .expect_err("must fail")
+            .is_repo_relocated());
+        assert!(!old_base_dir.join("data").exists());
+
+        let repo = RepoConfig::default(&new_base_dir, None).repo_blocking()?;
+        let relocated = repo.get(&project_dir)?.expect("must exist");
+        assert_eq!(dir_info.meta_id(), relocated.meta_id());
+        assert_eq!(
+            "content",
+            read_to_string(relocated.data_dir().join("file.txt"))?
+        );
+        assert!(repo.check()?.is_empty());
+
+        assert!(repo
+            .relocate(&old_base_dir)
+            .expect_err("must fail")
+            .is_directory_not_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn lock_data_dir_basics() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let project_dir = temp_dir.path().join("project");
+        create_dir_all(&project_dir)?;
+        let repo = RepoConfig::default(&temp_dir.path().join("repo"), None).repo_blocking()?;
+
+        let meta_id = MetaId::random();
+        assert!(repo.lock_data_dir(&meta_id).is_err());
+        assert!(!repo.container_dir().exists());
+
+        let dir_info = repo.init(&project_dir)?.expect("must succeed");
+        let lock = repo
+            .lock_data_dir(dir_info.meta_id())?
+            .expect("lock should be acquired");
+        let lock_path = lock.lock_path().to_path_buf();
+        drop(lock);
+
+        repo.remove(&project_dir)?.expect("must succeed");
+        assert!(lock_path.is_file());
+        assert!(repo.lock_data_dir(dir_info.meta_id()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn purge_basics() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let project_dir = temp_dir.path().join("project");
+        create_dir_all(&project_dir)?;
+        let base_dir = temp_dir.path().join("repo");
+
+        let repo = RepoConfig::default(&base_dir, None).repo_blocking()?;
+        let dir_info = repo.init(&project_dir)?.expect("must succeed");
+        write(dir_info.data_dir().join("file.txt"), "content")?;
+        repo.purge()?;
+
+        assert!(!dir_info.data_dir().exists());
+        assert!(!base_dir.join("data").exists());
+        assert!(!base_dir.join("links").exists());
+        assert!(!base_dir.join("config.yaml").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn relocate_rollback() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let project_dir = temp_dir.path().join("project");
+        create_dir_all(&project_dir)?;
+        let old_base_dir = temp_dir.path().join("old");
+        let new_base_dir = temp_dir.path().join("new");
+
+        // Nesting the data directory inside the shared directory makes the
+        // final move fail after earlier moves have succeeded
+        let mut config = RepoConfig::default(&old_base_dir, None);
+        config.container_dir = config.shared_dir.join("data");
+        let repo = config.clone().repo_blocking()?;
+        let dir_info = repo.init(&project_dir)?.expect("must succeed");
+        repo.relocate(&new_base_dir).expect_err("must fail");
+
+        assert!(!new_base_dir.join("config.yaml").exists());
+        assert!(old_base_dir.join("config.yaml").is_file());
+        let repo = config.repo_blocking()?;
+        let restored = repo.get(&project_dir)?.expect("must exist");
+        assert_eq!(dir_info.meta_id(), restored.meta_id());
+        assert!(repo.check()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn init_rolls_back() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let repo_dir = temp_dir.path().join("repo");
+        let project_dir = temp_dir.path().join("project");
+        create_dir_all(&project_dir)?;
+
+        let other_project_dir = temp_dir.path().join("other-project");
+        create_dir_all(&other_project_dir)?;
+
+        let repo = RepoConfig::default(&repo_dir, None).repo_blocking()?;
+        let other = repo.init(&other_project_dir)?.expect("must succeed");
+        let (_, link_path) = repo.inner.locate_link(&project_dir)?;
+
+        // A directory in place of the link file makes the second action fail
+        create_dir_all(&link_path)?;
+
+        let e = repo.init(&project_dir).expect_err("init must fail");
+        assert!(e.is_journal_failed());
+        assert_eq!(1, repo.list_manifests()?.len());
+        let data_dirs = read_dir(repo.container_dir())?
+            .filter_map(Result::ok)
+            .map(|e| e.path())
+            .filter(|p| p.is_dir())
+            .collect::<Vec<_>>();
+        assert_eq!(vec![other.data_dir().to_path_buf()], data_dirs);
+        Ok(())
     }
 }
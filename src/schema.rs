@@ -0,0 +1,363 @@
+// Copyright (c) 2023 Richard Cook
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+use crate::error::RepoError;
+use crate::result::RepoResult;
+use joatmon::{read_yaml_file, safe_write_file};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_yaml::{Mapping, Value};
+use std::path::Path;
+
+pub const CURRENT_SCHEMA_VERSION: u32 = 1;
+
+// Only the configuration file gained version 2: manifests and links are
+// unchanged so older versions of this crate can still read them
+pub const CURRENT_CONFIG_SCHEMA_VERSION: u32 = 2;
+
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SchemaKind {
+    Config,
+    Manifest,
+    Link,
+    Quarantine,
+    ProjectMarker,
+}
+
+impl SchemaKind {
+    const fn current_version(self) -> u32 {
+        match self {
+            Self::Config => CURRENT_CONFIG_SCHEMA_VERSION,
+            Self::Manifest | Self::Link | Self::Quarantine | Self::ProjectMarker => {
+                CURRENT_SCHEMA_VERSION
+            }
+        }
+    }
+}
+
+struct Migration {
+    kind: SchemaKind,
+    from_version: u32,
+    apply: fn(&mut Mapping),
+}
+
+const MIGRATIONS: [Migration; 6] = [
+    Migration {
+        kind: SchemaKind::Config,
+        from_version: 0,
+        apply: migrate_config_v0,
+    },
+    Migration {
+        kind: SchemaKind::Manifest,
+        from_version: 0,
+        apply: migrate_manifest_v0,
+    },
+    Migration {
+        kind: SchemaKind::Link,
+        from_version: 0,
+        apply: migrate_link_v0,
+    },
+    Migration {
+        kind: SchemaKind::Config,
+        from_version: 1,
+        apply: migrate_config_v1,
+    },
+    Migration {
+        kind: SchemaKind::Quarantine,
+        from_version: 0,
+        apply: migrate_unversioned_v0,
+    },
+    Migration {
+        kind: SchemaKind::ProjectMarker,
+        from_version: 0,
+        apply: migrate_unversioned_v0,
+    },
+];
+
+pub fn read_versioned<T>(kind: SchemaKind, path: &Path) -> RepoResult<T>
+where
+    T: DeserializeOwned,
+{
+    let value = read_yaml_file::<Value>(path).map_err(RepoError::other)?;
+    from_versioned_value(kind, path, value)
+}
+
+pub fn read_versioned_value(kind: SchemaKind, path: &Path) -> RepoResult<Value> {
+    let value = read_yaml_file::<Value>(path).map_err(RepoError::other)?;
+    Ok(upgrade(kind, path, value)?.0)
+}
+
+pub fn upgrade_value(kind: SchemaKind, path: &Path, value: Value) -> RepoResult<Value> {
+    Ok(upgrade(kind, path, value)?.0)
+}
+
+pub fn from_versioned_value<T>(kind: SchemaKind, path: &Path, value: Value) -> RepoResult<T>
+where
+    T: DeserializeOwned,
+{
+    let (value, _) = upgrade(kind, path, value)?;
+    serde_yaml::from_value(value).map_err(|e| RepoError::invalid_file(path, &e))
+}
+
+pub fn migrate_file<T>(kind: SchemaKind, path: &Path) -> RepoResult<bool>
+where
+    T: DeserializeOwned + Serialize,
+{
+    let value = read_yaml_file::<Value>(path).map_err(RepoError::other)?;
+    let (value, upgraded) = upgrade(kind, path, value)?;
+    if !upgraded {
+        return Ok(false);
+    }
+
+    let record =
+        serde_yaml::from_value::<T>(value).map_err(|e| RepoError::invalid_file(path, &e))?;
+    let yaml_str = serde_yaml::to_string(&record).map_err(RepoError::other)?;
+    safe_write_file(path, yaml_str, true).map_err(RepoError::other)?;
+    Ok(true)
+}
+
+fn upgrade(kind: SchemaKind, path: &Path, value: Value) -> RepoResult<(Value, bool)> {
+    let Value::Mapping(mut mapping) = value else {
+        return Err(RepoError::invalid_file(path, &"expected a mapping"));
+    };
+
+    let mut schema_version = match mapping.get(SCHEMA_VERSION_KEY) {
+        None => 0,
+        Some(value) => value
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| RepoError::invalid_file(path, &"invalid schema_version"))?,
+    };
+
+    let current_version = kind.current_version();
+    if schema_version > current_version {
+        return Err(RepoError::unsupported_schema_version(
+            path,
+            schema_version,
+            current_version,
+        ));
+    }
+
+    let upgraded = schema_version < current_version;
+    while schema_version < current_version {
+        let migration = MIGRATIONS
+            .iter()
+            .find(|m| m.kind == kind && m.from_version == schema_version)
+            .ok_or_else(|| {
+                RepoError::unsupported_schema_version(path, schema_version, current_version)
+            })?;
+        (migration.apply)(&mut mapping);
+        schema_version += 1;
+        mapping.insert(Value::from(SCHEMA_VERSION_KEY), Value::from(schema_version));
+    }
+
+    Ok((Value::Mapping(mapping), upgraded))
+}
+
+fn insert_default(mapping: &mut Mapping, key: &str, value: &str) {
+    let key = Value::from(key);
+    if !mapping.contains_key(&key) {
+        mapping.insert(key, Value::from(value));
+    }
+}
+
+fn migrate_config_v0(mapping: &mut Mapping) {
+    insert_default(mapping, "link_hash_algorithm", "md5");
+    insert_default(mapping, "path_policy", "verbatim");
+}
+
+const fn migrate_manifest_v0(_mapping: &mut Mapping) {}
+
+fn migrate_link_v0(mapping: &mut Mapping) {
+    insert_default(mapping, "hash_algorithm", "md5");
+}
+
+// Quarantine records and project markers were written without a schema
+// version before version 1 but are otherwise unchanged
+const fn migrate_unversioned_v0(_mapping: &mut Mapping) {}
+
+// Version 2 allows configuration paths relative to the base directory:
+// absolute paths written by version 1 remain valid
+const fn migrate_config_v1(_mapping: &mut Mapping) {}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::{
+        from_versioned_value, SchemaKind, CURRENT_CONFIG_SCHEMA_VERSION, CURRENT_SCHEMA_VERSION,
+    };
+    use crate::config::RepoConfig;
+    use crate::link::LinkRecord;
+    use crate::link_hash_algorithm::LinkHashAlgorithm;
+    use crate::manifest::ManifestRecord;
+    use crate::path_policy::PathPolicy;
+    use crate::quarantine::Quarantine;
+    use anyhow::Result;
+    use serde_yaml::Value;
+    use std::fs::{create_dir_all, read_to_string, write};
+    use std::path::Path;
+    use tempdir::TempDir;
+
+    const CONFIG_0_0_X: &str = include_str!("../fixtures/0.0.x/config.yaml");
+    const MANIFEST_0_0_X: &str = include_str!("../fixtures/0.0.x/manifest.yaml");
+    const LINK_0_0_X: &str = include_str!("../fixtures/0.0.x/link.yaml");
+
+    fn parse<T>(kind: SchemaKind, s: &str) -> Result<T>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let value = serde_yaml::from_str::<Value>(s)?;
+        Ok(from_versioned_value(kind, Path::new("test.yaml"), value)?)
+    }
+
+    #[test]
+    fn upgrade_config_0_0_x() -> Result<()> {
+        let config = parse::<RepoConfig>(SchemaKind::Config, CONFIG_0_0_X)?;
+        assert_eq!(CURRENT_CONFIG_SCHEMA_VERSION, config.schema_version);
+        assert_eq!(LinkHashAlgorithm::Md5, config.link_hash_algorithm);
+        assert_eq!(PathPolicy::Verbatim, config.path_policy);
+        assert_eq!(None, config.quarantine_dir);
+        Ok(())
+    }
+
+    #[test]
+    fn upgrade_manifest_0_0_x() -> Result<()> {
+        let record = parse::<ManifestRecord>(SchemaKind::Manifest, MANIFEST_0_0_X)?;
+        assert_eq!(CURRENT_SCHEMA_VERSION, record.schema_version);
+        assert_eq!(Path::new("/home/user/project"), record.original_project_dir);
+        assert!(record.extra.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn upgrade_link_0_0_x() -> Result<()> {
+        let record = parse::<LinkRecord>(SchemaKind::Link, LINK_0_0_X)?;
+        assert_eq!(CURRENT_SCHEMA_VERSION, record.schema_version);
+        assert_eq!(LinkHashAlgorithm::Md5, record.hash_algorithm);
+        assert!(record.extra.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn upgrade_quarantine_record_0_0_x() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let entry_dir = temp_dir.path().join("41941524f6da41dfa06fdb5c55f32a3d");
+        create_dir_all(&entry_dir)?;
+        let link_yaml = format!("- {}\n", LINK_0_0_X.trim_end().replace('\n', "\n  "));
+        write(
+            entry_dir.join("quarantine.yaml"),
+            format!(
+                "quarantined_at: 2023-12-01T17:36:05Z\n\
+                 meta_id: 41941524f6da41dfa06fdb5c55f32a3d\n\
+                 reason: unreferenced\n\
+                 links:\n{link_yaml}"
+            ),
+        )?;
+
+        let entries = Quarantine::new(temp_dir.path(), 7).list()?;
+        assert_eq!(1, entries.len());
+        assert_eq!(1, entries[0].links().len());
+        assert_eq!(LinkHashAlgorithm::Md5, entries[0].links()[0].hash_algorithm);
+        assert_eq!(
+            vec![Path::new("/home/user/project")],
+            entries[0].project_dirs()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unsupported_schema_version() {
+        let result = parse::<LinkRecord>(
+            SchemaKind::Link,
+            &format!(
+                "schema_version: {}\n{LINK_0_0_X}",
+                CURRENT_SCHEMA_VERSION + 1
+            ),
+        );
+        assert!(result
+            .expect_err("must fail")
+            .downcast_ref::<crate::error::RepoError>()
+            .expect("must be RepoError")
+            .is_unsupported_schema_version());
+    }
+
+    #[test]
+    fn invalid_file() {
+        let result = parse::<LinkRecord>(SchemaKind::Link, "created_at: 123\n");
+        assert!(result
+            .expect_err("must fail")
+            .downcast_ref::<crate::error::RepoError>()
+            .expect("must be RepoError")
+            .is_invalid_file());
+    }
+
+    #[test]
+    fn migrate_0_0_x_repo() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let base_dir = temp_dir.path().join("repo");
+        let data_dir = base_dir
+            .join("data")
+            .join("41941524f6da41dfa06fdb5c55f32a3d");
+        let links_dir = base_dir.join("links");
+        create_dir_all(&data_dir)?;
+        create_dir_all(&links_dir)?;
+
+        let config_path = base_dir.join("config.yaml");
+        let manifest_path = data_dir.join("manifest.yaml");
+        let link_path = links_dir.join("90722f2638004be06d790eaac9ac1f8a.yaml");
+        write(
+            &config_path,
+            CONFIG_0_0_X.replace("$BASE_DIR", &base_dir.display().to_string()),
+        )?;
+        write(&manifest_path, MANIFEST_0_0_X)?;
+        write(&link_path, LINK_0_0_X)?;
+
+        let repo = RepoConfig::default(&base_dir, None).repo_blocking()?;
+        assert_eq!(LinkHashAlgorithm::Md5, repo.link_hash_algorithm());
+        assert_eq!(1, repo.list_manifests()?.len());
+        assert_eq!(1, repo.list_links()?.len());
+
+        let mut migrated = repo.migrate()?;
+        migrated.sort();
+        let mut expected = vec![
+            config_path.clone(),
+            manifest_path.clone(),
+            link_path.clone(),
+        ];
+        expected.sort();
+        assert_eq!(expected, migrated);
+        assert!(repo.migrate()?.is_empty());
+
+        assert!(read_to_string(&config_path)?
+            .contains(&format!("schema_version: {CURRENT_CONFIG_SCHEMA_VERSION}")));
+        for path in [&manifest_path, &link_path] {
+            assert!(read_to_string(path)?
+                .contains(&format!("schema_version: {CURRENT_SCHEMA_VERSION}")));
+        }
+        assert!(read_to_string(&config_path)?.contains("link_hash_algorithm: md5"));
+        assert!(read_to_string(&link_path)?.contains("hash_algorithm: md5"));
+
+        assert_eq!(1, repo.list_manifests()?.len());
+        assert_eq!(1, repo.list_links()?.len());
+        Ok(())
+    }
+}
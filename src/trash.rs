@@ -19,19 +19,64 @@
 // OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
 // WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 //
+use crate::data_dir_lock::DataDirLock;
 use crate::error::RepoError;
-use crate::link::Link;
+use crate::link::{Link, LinkRecord};
 use crate::manifest::Manifest;
+use crate::meta_id::MetaId;
+use crate::meta_ref::resolve_meta_ref;
+use crate::quarantine::{Quarantine, QuarantineEntry};
+use crate::reachability::Reachability;
 use crate::repo::Repo;
 use crate::result::RepoResult;
-use std::collections::HashMap;
-use std::fmt::Debug;
-use std::fs::{remove_dir_all, remove_file};
+use crate::trash_policy::TrashPolicy;
+use chrono::{DateTime, Duration, Utc};
+use joatmon::safe_write_file;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
+use std::fs::{metadata, read_dir, remove_file, rename};
+use std::path::Path;
+
+const MAX_ACTIVITY_ENTRIES: usize = 1000;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TrashReason {
+    MissingProjectDir,
+    MissingManifest,
+    Unreferenced,
+    Unreachable,
+    Idle,
+    OverSizeLimit,
+}
+
+impl Display for TrashReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::MissingProjectDir => write!(f, "project directory does not exist"),
+            Self::MissingManifest => write!(f, "metadirectory does not exist"),
+            Self::Unreferenced => write!(f, "metadirectory is not referenced by any link"),
+            Self::Unreachable => write!(f, "project directory is on a volume that is not mounted"),
+            Self::Idle => write!(f, "project directory has not been modified recently"),
+            Self::OverSizeLimit => write!(f, "repository exceeds maximum data size"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TrashEntry<T> {
+    pub item: T,
+    pub reason: TrashReason,
+}
 
 #[derive(Debug)]
 pub struct Trash {
-    pub unreferenced_manifests: Vec<Manifest>,
-    pub invalid_links: Vec<Link>,
+    pub unreferenced_manifests: Vec<TrashEntry<Manifest>>,
+    pub invalid_links: Vec<TrashEntry<Link>>,
+    pub unreachable_links: Vec<TrashEntry<Link>>,
+    pub idle_links: Vec<TrashEntry<Link>>,
+    quarantine: Option<Quarantine>,
 }
 
 struct ManifestStatus {
@@ -41,7 +86,7 @@ struct ManifestStatus {
 
 struct LinkStatus {
     link: Link,
-    is_valid: bool,
+    invalid_reason: Option<TrashReason>,
 }
 
 impl Trash {
@@ -68,56 +113,589 @@ impl Trash {
                     l.link_id().clone(),
                     LinkStatus {
                         link: l,
-                        is_valid: true,
+                        invalid_reason: None,
                     },
                 )
             })
             .collect::<HashMap<_, _>>();
 
+        let reachability = Reachability::probe(&repo.as_read_only().config().protected_prefixes);
+
         for l in link_map.values_mut() {
             if l.link.project_dir().is_dir() {
                 match manifest_map.get_mut(l.link.meta_id()) {
                     Some(m) => m.is_referenced = true,
-                    None => l.is_valid = false,
+                    None => l.invalid_reason = Some(TrashReason::MissingManifest),
+                }
+            } else if reachability.is_unreachable(l.link.project_dir()) {
+                if let Some(m) = manifest_map.get_mut(l.link.meta_id()) {
+                    m.is_referenced = true;
                 }
+                l.invalid_reason = Some(TrashReason::Unreachable);
             } else {
-                l.is_valid = false;
+                l.invalid_reason = Some(TrashReason::MissingProjectDir);
             }
         }
 
-        let invalid_links = link_map
+        let (unreachable_links, invalid_links): (Vec<_>, Vec<_>) = link_map
             .into_values()
-            .filter(|x| !x.is_valid)
-            .map(|x| x.link)
-            .collect::<Vec<_>>();
+            .filter_map(|x| {
+                x.invalid_reason.map(|reason| TrashEntry {
+                    item: x.link,
+                    reason,
+                })
+            })
+            .partition(|x| x.reason == TrashReason::Unreachable);
         let unreferenced_manifests = manifest_map
             .into_values()
             .filter(|x| !x.is_referenced)
-            .map(|x| x.manifest)
+            .map(|x| TrashEntry {
+                item: x.manifest,
+                reason: TrashReason::Unreferenced,
+            })
             .collect::<Vec<_>>();
 
         Ok(Self {
             unreferenced_manifests,
             invalid_links,
+            unreachable_links,
+            idle_links: Vec::new(),
+            quarantine: repo.as_read_only().quarantine(),
         })
     }
 
+    pub fn compute_with_policy(repo: &Repo, policy: &TrashPolicy) -> RepoResult<Self> {
+        let now = Utc::now();
+        let mut trash = Self::compute(repo)?;
+
+        if let Some(days) = policy.link_idle_days {
+            trash.find_idle_links(repo, now - Duration::days(i64::from(days)))?;
+            if policy.trash_idle_links {
+                trash.expire_idle_links(repo)?;
+            }
+        }
+
+        let mut retained_manifests = match policy.unreferenced_retention_days {
+            Some(days) => {
+                let cutoff = now - Duration::days(i64::from(days));
+                let (expired, retained): (Vec<_>, Vec<_>) = trash
+                    .unreferenced_manifests
+                    .drain(..)
+                    .partition(|m| last_modified_manifest(&m.item) <= cutoff);
+                trash.unreferenced_manifests = expired;
+                retained
+            }
+            None if policy.max_data_size.is_some() => {
+                trash.unreferenced_manifests.drain(..).collect::<Vec<_>>()
+            }
+            None => Vec::new(),
+        };
+
+        if let Some(max_data_size) = policy.max_data_size {
+            let trashed_meta_ids = trash
+                .unreferenced_manifests
+                .iter()
+                .map(|m| m.item.meta_id().clone())
+                .collect::<HashSet<_>>();
+            let mut data_size = 0;
+            for m in repo.list_manifests()? {
+                if !trashed_meta_ids.contains(m.meta_id()) {
+                    data_size += m.disk_usage()?.bytes();
+                }
+            }
+
+            retained_manifests.sort_by_key(|m| last_modified_manifest(&m.item));
+            let mut retained_manifests = retained_manifests.into_iter();
+            while data_size > max_data_size {
+                let Some(m) = retained_manifests.next() else {
+                    break;
+                };
+                data_size = data_size.saturating_sub(m.item.disk_usage()?.bytes());
+                trash.unreferenced_manifests.push(TrashEntry {
+                    item: m.item,
+                    reason: TrashReason::OverSizeLimit,
+                });
+            }
+        }
+
+        Ok(trash)
+    }
+
+    pub fn list_quarantine(repo: &Repo) -> RepoResult<Vec<QuarantineEntry>> {
+        repo.as_read_only()
+            .quarantine()
+            .map_or_else(|| Ok(Vec::new()), |q| q.list())
+    }
+
+    pub fn resolve_quarantined_ref(repo: &Repo, s: &str) -> RepoResult<MetaId> {
+        let entries = Self::list_quarantine(repo)?;
+        let names = entries
+            .iter()
+            .map(|e| {
+                repo.as_read_only()
+                    .read_manifest_from_datadir(&e.data_dir())
+                    .ok()
+                    .and_then(|m| m.metadata().name.clone())
+            })
+            .collect::<Vec<_>>();
+        resolve_meta_ref(
+            s,
+            entries
+                .iter()
+                .zip(&names)
+                .map(|(e, name)| (e.meta_id(), name.as_deref())),
+        )
+    }
+
+    pub fn restore(repo: &Repo, meta_id: &MetaId) -> RepoResult<Option<Manifest>> {
+        let Some(quarantine) = repo.as_read_only().quarantine() else {
+            return Ok(None);
+        };
+
+        let Some(entry) = quarantine.get(meta_id)? else {
+            return Ok(None);
+        };
+
+        let data_dir = repo.as_read_only().make_data_dir(meta_id);
+        if data_dir.exists() {
+            return Ok(None);
+        }
+
+        let _data_dir_lock = DataDirLock::try_acquire(&data_dir)?
+            .ok_or_else(|| RepoError::could_not_lock(&DataDirLock::make_lock_path(&data_dir)))?;
+        rename(entry.data_dir(), &data_dir).map_err(RepoError::other)?;
+
+        for link_record in entry.links() {
+            let link_path = repo.as_read_only().make_link_path(&link_record.link_id);
+            if !link_path.exists() {
+                let yaml_str = serde_yaml::to_string(link_record).map_err(RepoError::other)?;
+                safe_write_file(&link_path, yaml_str, false).map_err(RepoError::other)?;
+            }
+        }
+
+        Quarantine::remove(&entry)?;
+
+        Ok(Some(repo.read_manifest(meta_id)?))
+    }
+
+    fn find_idle_links(&mut self, repo: &Repo, cutoff: DateTime<Utc>) -> RepoResult<()> {
+        let flagged_link_ids = self
+            .invalid_links
+            .iter()
+            .chain(&self.unreachable_links)
+            .map(|l| l.item.link_id().clone())
+            .collect::<HashSet<_>>();
+
+        for l in repo.list_links()? {
+            if !flagged_link_ids.contains(l.link_id()) && last_activity(repo, &l) <= cutoff {
+                self.idle_links.push(TrashEntry {
+                    item: l,
+                    reason: TrashReason::Idle,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn expire_idle_links(&mut self, repo: &Repo) -> RepoResult<()> {
+        self.invalid_links.append(&mut self.idle_links);
+
+        let invalid_link_ids = self
+            .invalid_links
+            .iter()
+            .map(|l| l.item.link_id().clone())
+            .collect::<HashSet<_>>();
+        let referenced_meta_ids = repo
+            .list_links()?
+            .into_iter()
+            .filter(|l| !invalid_link_ids.contains(l.link_id()))
+            .map(|l| l.meta_id().clone())
+            .collect::<HashSet<_>>();
+        let unreferenced_meta_ids = self
+            .unreferenced_manifests
+            .iter()
+            .map(|m| m.item.meta_id().clone())
+            .collect::<HashSet<_>>();
+
+        for m in repo.list_manifests()? {
+            if !referenced_meta_ids.contains(m.meta_id())
+                && !unreferenced_meta_ids.contains(m.meta_id())
+            {
+                self.unreferenced_manifests.push(TrashEntry {
+                    item: m,
+                    reason: TrashReason::Unreferenced,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
     #[must_use]
-    pub fn is_empty(&self) -> bool {
+    pub const fn is_empty(&self) -> bool {
         self.invalid_links.len() + self.unreferenced_manifests.len() == 0
     }
 
+    #[must_use]
+    pub const fn is_quarantined(&self) -> bool {
+        self.quarantine.is_some()
+    }
+
     pub fn empty(&mut self) -> RepoResult<()> {
-        for l in self.invalid_links.drain(..) {
-            remove_file(l.link_path())
-                .map_err(|_e| RepoError::could_not_delete_file(l.link_path()))?;
+        let mut link_records = HashMap::<MetaId, Vec<LinkRecord>>::new();
+        for l in &self.invalid_links {
+            link_records
+                .entry(l.item.meta_id().clone())
+                .or_default()
+                .push(l.item.record().clone());
         }
 
         for m in self.unreferenced_manifests.drain(..) {
-            remove_dir_all(m.data_dir())
-                .map_err(|_e| RepoError::could_not_delete_directory(m.data_dir()))?;
+            match &self.quarantine {
+                Some(quarantine) => quarantine.put(
+                    &m.item,
+                    link_records.remove(m.item.meta_id()).unwrap_or_default(),
+                    m.reason,
+                )?,
+                None => DataDirLock::remove_data_dir(m.item.data_dir())?,
+            }
         }
 
+        for l in self.invalid_links.drain(..) {
+            remove_file(l.item.link_path())
+                .map_err(|_e| RepoError::could_not_delete_file(l.item.link_path()))?;
+        }
+
+        if let Some(quarantine) = &self.quarantine {
+            quarantine.expire(&Utc::now())?;
+        }
+
+        Ok(())
+    }
+}
+
+fn last_modified(path: &Path) -> Option<DateTime<Utc>> {
+    metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .map(DateTime::<Utc>::from)
+}
+
+// A project is active if its metadirectory was created recently or if the
+// project directory or any of its immediate entries was modified: nested
+// files are not examined so that large project trees stay cheap to check
+fn last_activity(repo: &Repo, link: &Link) -> DateTime<Utc> {
+    let created_at = repo
+        .read_manifest(link.meta_id())
+        .map_or_else(|_| *link.created_at(), |m| *m.created_at());
+    shallow_modified(link.project_dir()).map_or(created_at, |t| t.max(created_at))
+}
+
+fn shallow_modified(dir: &Path) -> Option<DateTime<Utc>> {
+    let mut newest = last_modified(dir)?;
+    if let Ok(entries) = read_dir(dir) {
+        for entry in entries.flatten().take(MAX_ACTIVITY_ENTRIES) {
+            if let Some(t) = entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .ok()
+                .map(DateTime::<Utc>::from)
+            {
+                newest = newest.max(t);
+            }
+        }
+    }
+    Some(newest)
+}
+
+fn last_modified_manifest(manifest: &Manifest) -> DateTime<Utc> {
+    let created_at = *manifest.created_at();
+    last_modified(manifest.data_dir()).map_or(created_at, |t| t.max(created_at))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Trash, TrashReason};
+    use crate::config::RepoConfig;
+    use crate::data_dir_lock::DataDirLock;
+    use crate::trash_policy::TrashPolicy;
+    use anyhow::Result;
+    use chrono::{DateTime, Duration, Utc};
+    use serde_yaml::Value;
+    use std::fs::{create_dir_all, read_to_string, remove_dir_all, write, File};
+    use std::time::SystemTime;
+    use tempdir::TempDir;
+
+    #[test]
+    fn quarantine_and_restore() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let repo = RepoConfig::default(&temp_dir.path().join("repo"), None).repo_blocking()?;
+        let project_dir = temp_dir.path().join("project");
+        create_dir_all(&project_dir)?;
+
+        let dir_info = repo.init(&project_dir)?.expect("init must succeed");
+        repo.set_name(dir_info.meta_id(), Some("project"))?;
+        assert!(Trash::compute(&repo)?.is_empty());
+
+        remove_dir_all(&project_dir)?;
+        let mut trash = Trash::compute(&repo)?;
+        assert_eq!(1, trash.invalid_links.len());
+        assert_eq!(
+            TrashReason::MissingProjectDir,
+            trash.invalid_links[0].reason
+        );
+        assert_eq!(1, trash.unreferenced_manifests.len());
+        assert_eq!(
+            TrashReason::Unreferenced,
+            trash.unreferenced_manifests[0].reason
+        );
+
+        trash.empty()?;
+        assert!(!dir_info.data_dir().exists());
+        assert!(!dir_info.link_path().exists());
+
+        let quarantined = Trash::list_quarantine(&repo)?;
+        assert_eq!(1, quarantined.len());
+        assert_eq!(dir_info.meta_id(), quarantined[0].meta_id());
+        assert_eq!(vec![project_dir.as_path()], quarantined[0].project_dirs());
+        assert_eq!(
+            dir_info.meta_id(),
+            &Trash::resolve_quarantined_ref(&repo, "project")?
+        );
+
+        let lock = DataDirLock::try_acquire(dir_info.data_dir())?.expect("lock must be acquired");
+        assert!(Trash::restore(&repo, dir_info.meta_id()).is_err());
+        assert!(!dir_info.data_dir().exists());
+        drop(lock);
+
+        let manifest = Trash::restore(&repo, dir_info.meta_id())?.expect("restore must succeed");
+        assert_eq!(dir_info.data_dir(), manifest.data_dir());
+        assert!(dir_info.link_path().is_file());
+        assert!(Trash::list_quarantine(&repo)?.is_empty());
+        assert!(Trash::restore(&repo, dir_info.meta_id())?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn unreachable_links() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let mut config = RepoConfig::default(&temp_dir.path().join("repo"), None);
+        let volume_dir = temp_dir.path().join("volume");
+        config.protected_prefixes = vec![volume_dir.clone()];
+        let repo = config.repo_blocking()?;
+        let project_dir = volume_dir.join("project");
+
+        let dir_info = repo.init(&project_dir)?.expect("init must succeed");
+        let mut trash = Trash::compute(&repo)?;
+        assert!(trash.is_empty());
+        assert_eq!(1, trash.unreachable_links.len());
+        assert_eq!(TrashReason::Unreachable, trash.unreachable_links[0].reason);
+
+        trash.empty()?;
+        assert!(dir_info.data_dir().is_dir());
+        assert!(dir_info.link_path().is_file());
+        Ok(())
+    }
+
+    #[test]
+    fn policy_retention() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let repo = RepoConfig::default(&temp_dir.path().join("repo"), None).repo_blocking()?;
+        let project_dir = temp_dir.path().join("project");
+
+        repo.init(&project_dir)?.expect("init must succeed");
+
+        let trash = Trash::compute_with_policy(
+            &repo,
+            &TrashPolicy {
+                unreferenced_retention_days: Some(30),
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(1, trash.invalid_links.len());
+        assert!(trash.unreferenced_manifests.is_empty());
+
+        let trash = Trash::compute_with_policy(
+            &repo,
+            &TrashPolicy {
+                unreferenced_retention_days: Some(30),
+                max_data_size: Some(0),
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(1, trash.unreferenced_manifests.len());
+        assert_eq!(
+            TrashReason::OverSizeLimit,
+            trash.unreferenced_manifests[0].reason
+        );
+
+        let trash = Trash::compute_with_policy(
+            &repo,
+            &TrashPolicy {
+                unreferenced_retention_days: Some(0),
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(1, trash.unreferenced_manifests.len());
+        assert_eq!(
+            TrashReason::Unreferenced,
+            trash.unreferenced_manifests[0].reason
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn policy_idle_links() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let repo = RepoConfig::default(&temp_dir.path().join("repo"), None).repo_blocking()?;
+        let project_dir = temp_dir.path().join("project");
+        create_dir_all(&project_dir)?;
+
+        repo.init(&project_dir)?.expect("init must succeed");
+        assert!(Trash::compute_with_policy(&repo, &TrashPolicy::default())?.is_empty());
+
+        let trash = Trash::compute_with_policy(
+            &repo,
+            &TrashPolicy {
+                link_idle_days: Some(30),
+                ..Default::default()
+            },
+        )?;
+        assert!(trash.is_empty());
+
+        let trash = Trash::compute_with_policy(
+            &repo,
+            &TrashPolicy {
+                link_idle_days: Some(0),
+                ..Default::default()
+            },
+        )?;
+        assert!(trash.is_empty());
+        assert_eq!(1, trash.idle_links.len());
+        assert_eq!(TrashReason::Idle, trash.idle_links[0].reason);
+
+        let trash = Trash::compute_with_policy(
+            &repo,
+            &TrashPolicy {
+                link_idle_days: Some(0),
+                trash_idle_links: true,
+                ..Default::default()
+            },
+        )?;
+        assert!(trash.idle_links.is_empty());
+        assert_eq!(1, trash.invalid_links.len());
+        assert_eq!(TrashReason::Idle, trash.invalid_links[0].reason);
+        assert_eq!(1, trash.unreferenced_manifests.len());
+        Ok(())
+    }
+
+    #[test]
+    fn policy_idle_links_recent_edits() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let repo = RepoConfig::default(&temp_dir.path().join("repo"), None).repo_blocking()?;
+        let project_dir = temp_dir.path().join("project");
+        let src_dir = project_dir.join("src");
+        create_dir_all(&src_dir)?;
+        let nested_file_path = src_dir.join("main.rs");
+        write(&nested_file_path, "fn main() {}")?;
+        let file_path = project_dir.join("Cargo.toml");
+        write(&file_path, "")?;
+
+        let dir_info = repo.init(&project_dir)?.expect("init must succeed");
+        let old = SystemTime::from(Utc::now() - Duration::days(365));
+        let manifest_path = dir_info.manifest_path();
+        let mut value = serde_yaml::from_str::<Value>(&read_to_string(manifest_path)?)?;
+        value["created_at"] = Value::from(DateTime::<Utc>::from(old).to_rfc3339());
+        write(manifest_path, serde_yaml::to_string(&value)?)?;
+        for path in [&nested_file_path, &src_dir, &file_path, &project_dir] {
+            File::open(path)?.set_modified(old)?;
+        }
+
+        let policy = TrashPolicy {
+            link_idle_days: Some(30),
+            ..Default::default()
+        };
+        let trash = Trash::compute_with_policy(&repo, &policy)?;
+        assert_eq!(1, trash.idle_links.len());
+
+        // Only the project directory and its immediate entries are examined
+        File::open(&nested_file_path)?.set_modified(SystemTime::now())?;
+        assert_eq!(
+            1,
+            Trash::compute_with_policy(&repo, &policy)?.idle_links.len()
+        );
+
+        File::open(&file_path)?.set_modified(SystemTime::now())?;
+        assert!(Trash::compute_with_policy(&repo, &policy)?
+            .idle_links
+            .is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn policy_max_data_size() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let repo = RepoConfig::default(&temp_dir.path().join("repo"), None).repo_blocking()?;
+        let project_dir = temp_dir.path().join("project");
+
+        repo.init(&project_dir)?.expect("init must succeed");
+
+        let trash = Trash::compute_with_policy(
+            &repo,
+            &TrashPolicy {
+                max_data_size: Some(u64::MAX),
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(1, trash.invalid_links.len());
+        assert!(trash.unreferenced_manifests.is_empty());
+
+        let trash = Trash::compute_with_policy(
+            &repo,
+            &TrashPolicy {
+                max_data_size: Some(0),
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(1, trash.unreferenced_manifests.len());
+        assert_eq!(
+            TrashReason::OverSizeLimit,
+            trash.unreferenced_manifests[0].reason
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn quarantine_retention() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let mut config = RepoConfig::default(&temp_dir.path().join("repo"), None);
+        config.quarantine_retention_days = 0;
+        let repo = config.repo_blocking()?;
+        let project_dir = temp_dir.path().join("project");
+
+        let dir_info = repo.init(&project_dir)?.expect("init must succeed");
+        Trash::compute(&repo)?.empty()?;
+        assert!(!dir_info.data_dir().exists());
+        assert!(Trash::list_quarantine(&repo)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn no_quarantine() -> Result<()> {
+        let temp_dir = TempDir::new("joat-repo-test")?;
+        let mut config = RepoConfig::default(&temp_dir.path().join("repo"), None);
+        config.quarantine_dir = None;
+        let repo = config.repo_blocking()?;
+        let project_dir = temp_dir.path().join("project");
+
+        let dir_info = repo.init(&project_dir)?.expect("init must succeed");
+        let mut trash = Trash::compute(&repo)?;
+        assert!(!trash.is_quarantined());
+        trash.empty()?;
+        assert!(!dir_info.data_dir().exists());
+        assert!(Trash::list_quarantine(&repo)?.is_empty());
+        assert!(Trash::restore(&repo, dir_info.meta_id())?.is_none());
         Ok(())
     }
 }
@@ -0,0 +1,56 @@
+// Copyright (c) 2023 Richard Cook
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(default)]
+pub struct TrashPolicy {
+    /// Keep unreferenced metadirectories for this many days. Age is
+    /// measured from the newer of the metadirectory's creation time and
+    /// the modification time of its data directory, not from the time at
+    /// which its last link was removed
+    pub unreferenced_retention_days: Option<u32>,
+
+    /// Report links as idle once neither their project directory nor any
+    /// of its immediate entries has been modified for this many days
+    pub link_idle_days: Option<u32>,
+
+    /// Remove idle links, and trash metadirectories that are no longer
+    /// referenced as a result, instead of only reporting them
+    pub trash_idle_links: bool,
+
+    /// Trash retained unreferenced metadirectories, oldest first, until
+    /// total data size is at most this many bytes. Without a retention
+    /// period, all unreferenced metadirectories are retained up to this
+    /// limit
+    pub max_data_size: Option<u64>,
+}
+
+impl TrashPolicy {
+    #[must_use]
+    pub const fn is_default(&self) -> bool {
+        self.unreferenced_retention_days.is_none()
+            && self.link_idle_days.is_none()
+            && !self.trash_idle_links
+            && self.max_data_size.is_none()
+    }
+}